@@ -1,4 +1,5 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MoveItem } from "./MoveItem";
 import type { PositionAndMove } from "./PositionAndMove";
 
-export interface BestPos { pos: PositionAndMove | null, deep_eval: number, }
\ No newline at end of file
+export interface BestPos { pos: PositionAndMove | null, deep_eval: number, pv: Array<MoveItem>, }
\ No newline at end of file
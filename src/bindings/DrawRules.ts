@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface DrawRules { repetitions: number, kings_only_moves: number | null, quiet_moves: number | null, russian_endings: boolean, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface EvalParams { man: number, king: number, mobility: number, advancement: number, center: number, back_rank: number, tempo: number, cell_grade: number, }
\ No newline at end of file
@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FinalSelection } from "./FinalSelection";
+import type { Rollout } from "./Rollout";
+
+export interface MctsConfig { exploration: number, rollout: Rollout, rollout_depth: number, alpha_beta_depth: number, first_play_urgency: number | null, selection: FinalSelection, passes: number, max_nodes: number, info_every: number, }
\ No newline at end of file
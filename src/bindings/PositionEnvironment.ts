@@ -1,5 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EvalParams } from "./EvalParams";
 import type { Grade } from "./Grade";
+import type { RuleSet } from "./RuleSet";
 import type { Vector } from "./Vector";
 
-export interface PositionEnvironment { size: number, king_row_black: number, king_row_white: number, vectors_map: Array<Array<Vector<number>>>, board_to_pack: Array<number>, pack_to_board: Array<number>, cell_grade: Array<Grade>, }
\ No newline at end of file
+export interface PositionEnvironment { size: number, king_row_black: number, king_row_white: number, vectors_map: Array<Array<Vector<number>>>, board_to_pack: Array<number>, pack_to_board: Array<number>, cell_grade: Array<Grade>, rules: RuleSet, eval: EvalParams, }
\ No newline at end of file
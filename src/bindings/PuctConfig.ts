@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface PuctConfig { c_puct: number, batch_size: number, root_noise: boolean, dirichlet_alpha: number, noise_fraction: number, first_play_urgency: number, passes: number, }
\ No newline at end of file
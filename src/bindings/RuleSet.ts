@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ColorType } from "./ColorType";
+import type { DrawRules } from "./DrawRules";
+import type { Variant } from "./Variant";
+
+export interface RuleSet { variant: Variant, size: number, first_move: ColorType, flying_kings: boolean, men_capture_backward: boolean, majority_capture: boolean, promote_mid_capture: boolean, draw_rules: DrawRules, pdn_game_type: number, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SearchStats } from "./SearchStats";
+
+export interface SearchInfo { stats: SearchStats, score: number, pv: Array<string>, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface SearchStats { nodes: bigint, depth: number, cutoffs: bigint, tt_hits: bigint, time_ms: number, nps: bigint, }
\ No newline at end of file
@@ -7,7 +7,7 @@ use serde::Serialize;
 use std::cmp::Ordering;
 use std::rc::Rc;
 use crate::color::Color::{Black, White};
-use crate::PositionHistory::FinishType::{BlackWin, Draw1, Draw2, Draw3, Draw4, Draw5, WhiteWin};
+use crate::PositionHistory::FinishType::{BlackWin, Draw1, Draw2, Draw3, Draw4, Draw5, Draw6, WhiteWin};
 
 #[wasm_bindgen]
 #[derive(Serialize, Debug)]
@@ -52,6 +52,10 @@ impl PositionHistory {
             list: vec![]
         }
     }
+    pub fn iter(&self) -> std::slice::Iter<'_, Rc<RefCell<PositionAndMove>>> {
+        self.list.iter()
+    }
+
     pub fn last(&mut self) -> Rc<RefCell<PositionAndMove>> {
         self.list.last().unwrap().clone()
     }
@@ -73,6 +77,16 @@ impl PositionHistory {
         self.list.pop()
     }
 
+    // moves of kings without strikes at the end of the game, the plies the quiet move rule counts
+    pub fn quiet_moves(&self) -> usize {
+        let is_quiet = |x: &Rc<RefCell<PositionAndMove>>| {
+            let x = x.borrow();
+            x.mov.as_ref().is_some_and(|mov| mov.strike.is_none() && !mov.is_king_move()
+                && x.pos.cells[mov.to()].as_ref().is_some_and(|piece| piece.is_king))
+        };
+        self.list.iter().rev().take_while(|x| is_quiet(x)).count()
+    }
+
     pub fn finish_check(&mut self) -> Option<FinishType> {
         let mut i = self.list.len();
         if i == 0 { return None; }
@@ -87,6 +101,12 @@ impl PositionHistory {
 
 
         let environment = current.borrow().pos.environment.clone();
+        let draw_rules = &environment.rules.draw_rules;
+        if let Some(limit) = draw_rules.quiet_moves {
+            if self.quiet_moves() >= limit {
+                return Some(Draw6);
+            }
+        }
         if current.borrow_mut().pos.state.get_count(White).king > 0 &&
             current.borrow_mut().pos.state.get_count(Black).king > 0 {
             i -= 1;
@@ -102,7 +122,7 @@ impl PositionHistory {
                 (state.get_count(White).king == 1 && state.get_count(Black).king >= 3) ||
                     (state.get_count(Black).king == 1 && state.get_count(White).king >= 3)
             };
-            if is_triangle(&mut current.borrow_mut().pos.state) {
+            if draw_rules.russian_endings && is_triangle(&mut current.borrow_mut().pos.state) {
                 if current.borrow().pos.state.triangle_start_at.is_none()
                     || current.borrow().pos.state.triangle_start_at.unwrap() > i {
                     current.borrow_mut().pos.state.triangle_start_at = Some(i);
@@ -111,31 +131,38 @@ impl PositionHistory {
                 }
             } else { current.borrow_mut().pos.state.triangle_start_at = None; }
 
-            if i < 1 { return None; }
-
-
             // 1) если в течение 15 ходов игроки делали ходы только дамками, не передвигая
             // простых шашек и не производя взятия.
-            if pos_history[i].borrow().pos
-                .cells[pos_history[i].borrow().mov.as_ref().unwrap().to()].as_ref().unwrap().is_king {
+            let is_kings_only = {
+                let x = current.borrow();
+                x.mov.as_ref().is_some_and(|mov| mov.strike.is_none()
+                    && x.pos.cells[mov.to()].as_ref().is_some_and(|piece| piece.is_king && !mov.is_king_move()))
+            };
+            if is_kings_only {
                 if current.borrow().pos.state.kings_only_move_start_at.is_none() ||
                     current.borrow().pos.state.kings_only_move_start_at.unwrap() > i {
-                    current.borrow_mut().pos.state.kings_only_move_start_at = Some(i);
+                    // the count goes on from the previous position, the positions are copied before it is set
+                    let start_at = if i > 0 { pos_history[i - 1].borrow().pos.state.kings_only_move_start_at } else { None };
+                    current.borrow_mut().pos.state.kings_only_move_start_at = Some(start_at.unwrap_or(i));
                 }
-                if i - current.borrow().pos.state.kings_only_move_start_at.unwrap() > 15 {
-                    return Some(Draw1);
+                if let Some(limit) = draw_rules.kings_only_moves {
+                    if i - current.borrow().pos.state.kings_only_move_start_at.unwrap() + 1 >= limit {
+                        return Some(Draw1);
+                    }
                 }
             } else {
                 current.borrow_mut().pos.state.kings_only_move_start_at = None;
             }
 
+            if i < 1 { return None; }
+
             // 2) если три раза повторяется одна и та же позиция
             current.borrow_mut().pos.state.repeats = 0;
             let mut j = i - 1;
             while pos_history[j].borrow().pos.state == current.borrow().pos.state {
-                if current.borrow().pos == pos_history[j].borrow().pos {
+                if current.borrow().pos.zobrist() == pos_history[j].borrow().pos.zobrist() {
                     current.borrow_mut().pos.state.repeats += 1;
-                    if current.borrow().pos.state.repeats > 2 {
+                    if current.borrow().pos.state.repeats >= draw_rules.repetitions {
                         return Some(Draw2);
                     }
                 }
@@ -150,6 +177,7 @@ impl PositionHistory {
             // в 2- и 3-фигурных окончаниях — 5 ходов,
             // в 4- и 5-фигурных окончаниях — 30 ходов,
             // в 6- и 7-фигурных окончаниях — 60 ходов;
+            if !draw_rules.russian_endings { return None; }
             if pos_history[i - 1].borrow().pos.state == pos_history[i].borrow_mut().pos.state {
                 if current.borrow().pos.state.power_equal_start_at.is_none()
                     || current.borrow().pos.state.power_equal_start_at.unwrap() > i - 1 {
@@ -209,6 +237,8 @@ pub enum FinishType {
     Draw3,
     Draw4,
     Draw5,
+    // no strikes and no men moved for the number of moves the rules set
+    Draw6,
     BlackWin,
     WhiteWin,
 }
@@ -226,7 +256,7 @@ impl PartialEq<Self> for FinishType {
     fn eq(&self, other: &Self) -> bool {
         let is_draw = |x: &FinishType| {
             match x {
-                Draw1 | Draw2 | Draw3 | Draw4 | Draw5 => { true }
+                Draw1 | Draw2 | Draw3 | Draw4 | Draw5 | Draw6 => { true }
                 _ => { false }
             }
         };
@@ -0,0 +1,435 @@
+use crate::color::Color;
+use crate::moves::{BoardPos, QuietMove, StraightStrike};
+use crate::moves_list::{MoveItem, MoveList};
+use crate::position::Position;
+use crate::rules::RuleSet;
+
+pub type Bits = u64;
+
+// Padded ("ghost square") layout: a dark square at (row, col) lives in bit
+// ((size + 1) * row + col) / 2, so every diagonal step is a constant shift.
+// Wrapping off the left/right edge lands on a ghost bit outside `valid`.
+// Fits in u64 for boards up to 10x10.
+#[derive(Clone, Debug)]
+pub struct BitGeometry {
+    pub size: i8,
+    // 0..3 (0 - UR, 1 - UL, 2 - DL, 3 - DR), same as Vector::direction
+    shift: [u32; 4],
+    valid: Bits,
+    king_row_white: Bits,
+    king_row_black: Bits,
+    pack_to_bit: Vec<Bits>,
+    bit_to_pack: Vec<BoardPos>,
+    // rule flags the generator depends on
+    flying_kings: bool,
+    men_capture_backward: bool,
+    promote_mid_capture: bool,
+    majority_capture: bool,
+}
+
+impl BitGeometry {
+    pub fn new(rules: &RuleSet) -> Option<BitGeometry> {
+        let size = rules.size;
+        if size % 2 != 0 || !(4..=10).contains(&size) { return None; }
+        let s = size as usize;
+        let mut pack_to_bit = Vec::with_capacity(s * s / 2);
+        let mut bit_to_pack = vec![BoardPos::MAX; 64];
+        let mut valid: Bits = 0;
+        let mut king_row_white: Bits = 0;
+        let mut king_row_black: Bits = 0;
+        for row in 0..s {
+            for col in 0..s {
+                if (row + col) % 2 != 0 { continue; }
+                let i = ((s + 1) * row + col) / 2;
+                let bit: Bits = 1 << i;
+                bit_to_pack[i] = pack_to_bit.len();
+                pack_to_bit.push(bit);
+                valid |= bit;
+                if row == 0 { king_row_black |= bit; }
+                if row == s - 1 { king_row_white |= bit; }
+            }
+        }
+        let half = (s / 2) as u32;
+        Some(BitGeometry {
+            size,
+            shift: [half + 1, half, half + 1, half],
+            valid,
+            king_row_white,
+            king_row_black,
+            pack_to_bit,
+            bit_to_pack,
+            flying_kings: rules.flying_kings,
+            men_capture_backward: rules.men_capture_backward,
+            promote_mid_capture: rules.promote_mid_capture,
+            majority_capture: rules.majority_capture,
+        })
+    }
+
+    #[inline]
+    pub fn step(&self, bits: Bits, direction: i8) -> Bits {
+        let sh = self.shift[direction as usize];
+        if direction < 2 { (bits << sh) & self.valid } else { (bits >> sh) & self.valid }
+    }
+
+    #[inline]
+    pub fn bit(&self, pos: BoardPos) -> Bits {
+        self.pack_to_bit[pos]
+    }
+
+    #[inline]
+    pub fn pos(&self, bit: Bits) -> BoardPos {
+        self.bit_to_pack[bit.trailing_zeros() as usize]
+    }
+
+    #[inline]
+    pub fn king_row(&self, color: Color) -> Bits {
+        if color == Color::White { self.king_row_white } else { self.king_row_black }
+    }
+}
+
+struct BitIter(Bits);
+
+impl Iterator for BitIter {
+    type Item = Bits;
+    fn next(&mut self) -> Option<Bits> {
+        if self.0 == 0 { return None; }
+        let bit = self.0 & self.0.wrapping_neg();
+        self.0 ^= bit;
+        Some(bit)
+    }
+}
+
+// what make_move changed, so unmake_move can restore it without a copy
+#[derive(Clone, Copy, Debug)]
+pub struct BitUndo {
+    from: Bits,
+    to: Bits,
+    was_king: bool,
+    taken: Bits,
+    taken_kings: Bits,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitPosition {
+    pub white: Bits,
+    pub black: Bits,
+    pub kings: Bits,
+    pub next_move: Color,
+    // pieces already jumped in the strike chain being generated
+    stricken: Bits,
+}
+
+impl BitPosition {
+    pub fn new(next_move: Color) -> BitPosition {
+        BitPosition { white: 0, black: 0, kings: 0, next_move, stricken: 0 }
+    }
+
+    pub fn from_position(geometry: &BitGeometry, position: &Position) -> BitPosition {
+        let color = position.next_move.unwrap_or_else(|| panic!("Color of next move undefined!"));
+        let mut bits = BitPosition::new(color);
+        for piece in position.cells.iter().flatten() {
+            let bit = geometry.bit(piece.pos);
+            if piece.color == Color::White { bits.white |= bit } else { bits.black |= bit }
+            if piece.is_king { bits.kings |= bit }
+        }
+        bits
+    }
+
+    // adds the piece, or removes it when it is there already
+    pub fn toggle(&mut self, geometry: &BitGeometry, pos: BoardPos, color: Color, is_king: bool) {
+        let bit = geometry.bit(pos);
+        *self.pieces_mut(color) ^= bit;
+        if is_king { self.kings ^= bit }
+    }
+
+    #[inline]
+    pub fn occupied(&self) -> Bits {
+        self.white | self.black
+    }
+
+    #[inline]
+    pub fn pieces(&self, color: Color) -> Bits {
+        if color == Color::White { self.white } else { self.black }
+    }
+
+    #[inline]
+    fn pieces_mut(&mut self, color: Color) -> &mut Bits {
+        if color == Color::White { &mut self.white } else { &mut self.black }
+    }
+
+    pub fn make_move(&mut self, geometry: &BitGeometry, move_item: &MoveItem) -> BitUndo {
+        let from = geometry.bit(move_item.from());
+        let to = geometry.bit(move_item.to());
+        let taken = match &move_item.strike {
+            Some(strike) => strike.vec.iter().fold(0, |acc, x| acc | geometry.bit(x.take)),
+            None => 0
+        };
+        let undo = BitUndo {
+            from,
+            to,
+            was_king: self.kings & from != 0,
+            taken,
+            taken_kings: self.kings & taken,
+        };
+        let color = self.next_move;
+        *self.pieces_mut(color) &= !from;
+        *self.pieces_mut(color) |= to;
+        *self.pieces_mut(!color) &= !taken;
+        self.kings &= !(from | taken);
+        if undo.was_king || move_item.is_king_move() { self.kings |= to; }
+        self.next_move = !color;
+        undo
+    }
+
+    pub fn unmake_move(&mut self, undo: &BitUndo) {
+        let color = !self.next_move;
+        *self.pieces_mut(color) &= !undo.to;
+        *self.pieces_mut(color) |= undo.from;
+        *self.pieces_mut(!color) |= undo.taken;
+        self.kings &= !undo.to;
+        if undo.was_king { self.kings |= undo.from; }
+        self.kings |= undo.taken_kings;
+        self.next_move = color;
+    }
+
+    // mirrors Position::get_vectors: directions that leave the board are skipped
+    fn directions(geometry: &BitGeometry, bit: Bits, color: Color, is_king: bool, for_strike: bool) -> Vec<i8> {
+        let all = is_king || (for_strike && geometry.men_capture_backward);
+        let (a, b) = if all { (0, 4) } else if color == Color::White { (0, 2) } else { (2, 4) };
+        (a..b).filter(|d| geometry.step(bit, *d) != 0).collect()
+    }
+
+    // mirrors Position::straight_strike: returns taken square and free squares behind it
+    fn straight_strike(&self, geometry: &BitGeometry, from: Bits, direction: i8, color: Color, is_king: bool)
+                       -> Option<(Bits, Vec<Bits>)> {
+        let flying = is_king && geometry.flying_kings;
+        let occupied = self.occupied();
+        let mut cur = geometry.step(from, direction);
+        loop {
+            if cur == 0 { return None; }
+            if cur & occupied != 0 {
+                let mut land = geometry.step(cur, direction);
+                if land == 0 || land & occupied != 0 || cur & self.pieces(color) != 0
+                    || cur & self.stricken != 0 {
+                    return None;
+                }
+                let mut landings = vec![];
+                while land != 0 && land & occupied == 0 {
+                    landings.push(land);
+                    if !flying { break; }
+                    land = geometry.step(land, direction);
+                }
+                return Some((cur, landings));
+            }
+            if !flying { return None; }
+            cur = geometry.step(cur, direction);
+        }
+    }
+
+    fn get_strike_list(&mut self, geometry: &BitGeometry, from: Bits, move_list: &mut MoveList,
+                       ban_directions: &[i8], for_front: bool) -> bool {
+        let color = if self.white & from != 0 { Color::White } else { Color::Black };
+        let is_king = self.kings & from != 0;
+        let mut success_call = false;
+        let directions = BitPosition::directions(geometry, from, color, is_king, true);
+        for direction in directions.into_iter().filter(|d| !ban_directions.contains(d)) {
+            if let Some((take, landings)) = self.straight_strike(geometry, from, direction, color, is_king) {
+                success_call = true;
+                let king_move = !is_king && landings[0] & geometry.king_row(color) != 0;
+                let mut straight_strike = StraightStrike {
+                    v: landings.iter().map(|x| geometry.pos(*x)).collect(),
+                    from: geometry.pos(from),
+                    to: geometry.pos(landings[0]),
+                    take: geometry.pos(take),
+                    king_move,
+                };
+                // crowned on the way only if the rules allow it, the final landing is checked below
+                let promote = king_move && geometry.promote_mid_capture;
+                let mut ban_directions = vec![(direction + 2) % 4];
+                let mut recurrent_chain = false;
+                for (land, pos) in landings.iter().zip(straight_strike.v.clone()) {
+                    let king_bits = if is_king { from | land } else if promote { *land } else { 0 };
+                    straight_strike.to = pos;
+                    *self.pieces_mut(color) ^= from | land;
+                    self.kings ^= king_bits;
+                    self.stricken |= take;
+                    move_list.current_chain.vec.push(StraightStrike { king_move: promote, ..straight_strike.clone() });
+                    if promote { move_list.current_chain.king_move = true; }
+                    if self.get_strike_list(geometry, *land, move_list, &ban_directions, for_front) {
+                        recurrent_chain = true;
+                    }
+                    move_list.current_chain.vec.pop();
+                    if promote { move_list.current_chain.king_move = false; }
+                    self.stricken &= !take;
+                    self.kings ^= king_bits;
+                    *self.pieces_mut(color) ^= from | land;
+                    if !for_front && ban_directions.len() < 2 {
+                        ban_directions.push(direction);
+                    }
+                }
+                if !recurrent_chain {
+                    for pos in straight_strike.v.clone() {
+                        let mut strike_move = straight_strike.clone();
+                        strike_move.to = pos;
+                        let mut chain = move_list.current_chain.clone();
+                        if king_move { chain.king_move = true; }
+                        chain.vec.push(strike_move);
+                        move_list.list.push(MoveItem { strike: Some(chain), mov: None });
+                    }
+                }
+            }
+        }
+        success_call
+    }
+
+    fn get_quiet_move_list(&self, geometry: &BitGeometry, from: Bits, move_list: &mut MoveList) {
+        let color = self.next_move;
+        let is_king = self.kings & from != 0;
+        let occupied = self.occupied();
+        for direction in BitPosition::directions(geometry, from, color, is_king, false) {
+            let mut to = geometry.step(from, direction);
+            while to != 0 && to & occupied == 0 {
+                move_list.list.push(MoveItem {
+                    mov: Some(QuietMove {
+                        from: geometry.pos(from),
+                        to: geometry.pos(to),
+                        king_move: !is_king && to & geometry.king_row(color) != 0,
+                    }),
+                    strike: None,
+                });
+                if !is_king || !geometry.flying_kings { break; }
+                to = geometry.step(to, direction);
+            }
+        }
+    }
+
+    pub fn get_move_list(&mut self, geometry: &BitGeometry, for_front: bool) -> MoveList {
+        let pieces = self.pieces(self.next_move);
+        let mut move_list = MoveList::new();
+        for from in BitIter(pieces) {
+            self.get_strike_list(geometry, from, &mut move_list, &[], for_front);
+        }
+        if move_list.list.is_empty() {
+            for from in BitIter(pieces) {
+                self.get_quiet_move_list(geometry, from, &mut move_list);
+            }
+        } else if geometry.majority_capture {
+            move_list.retain_longest_strikes();
+        }
+        move_list
+    }
+
+    pub fn perft(&mut self, geometry: &BitGeometry, depth: u32) -> u64 {
+        if depth == 0 { return 1; }
+        let move_list = self.get_move_list(geometry, false);
+        if depth == 1 { return move_list.list.len() as u64; }
+        let mut nodes = 0;
+        for move_item in &move_list.list {
+            let undo = self.make_move(geometry, move_item);
+            nodes += self.perft(geometry, depth - 1);
+            self.unmake_move(&undo);
+        }
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use crate::bitboard::{BitGeometry, BitPosition};
+    use crate::color::Color;
+    use crate::game::Game;
+    use crate::piece::Piece;
+    use crate::position::Position;
+    use crate::position_environment::PositionEnvironment;
+    use crate::rules::{RuleSet, Variant};
+
+    fn cross_check(pos: &mut Position) {
+        let geometry = BitGeometry::new(&pos.environment.rules).unwrap();
+        for for_front in [false, true] {
+            let by_cells = pos.get_move_list_by_cells(for_front);
+            let by_bits = BitPosition::from_position(&geometry, pos).get_move_list(&geometry, for_front);
+            assert_eq!(serde_json::to_string(&by_cells.list).unwrap(),
+                       serde_json::to_string(&by_bits.list).unwrap());
+            // the bitboards the position keeps up to date with its moves
+            let kept = pos.get_move_list(for_front);
+            assert_eq!(serde_json::to_string(&by_bits.list).unwrap(),
+                       serde_json::to_string(&kept.list).unwrap());
+        }
+    }
+
+    #[test]
+    fn geometry_edges() {
+        for size in [8, 10] {
+            let geometry = BitGeometry::new(&RuleSet::russian(size)).unwrap();
+            let environment = PositionEnvironment::new(size);
+            for pos in 0..(size as usize * size as usize / 2) {
+                let mut directions = 0;
+                for v in environment.get_vectors(pos) {
+                    let mut bit = geometry.bit(pos);
+                    for point in &v.points[1..] {
+                        bit = geometry.step(bit, v.direction);
+                        assert_eq!(geometry.pos(bit), *point);
+                    }
+                    assert_eq!(geometry.step(bit, v.direction), 0);
+                    directions |= 1 << v.direction;
+                }
+                for direction in 0..4 {
+                    if directions & (1 << direction) == 0 {
+                        assert_eq!(geometry.step(geometry.bit(pos), direction), 0);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn strike_positions() {
+        let mut game = Game::new(8);
+        game.current_position.next_move = Some(Color::White);
+        game.insert_piece(Piece::new(game.to_pack(47), Color::White, false));
+        game.insert_piece(Piece::new(game.to_pack(63), Color::White, false));
+        game.insert_piece(Piece::new(game.to_pack(15), Color::White, true));
+        vec![54, 43, 20].iter()
+            .for_each(|pos| game.insert_piece(Piece::new(game.to_pack(*pos), Color::Black, false)));
+        cross_check(&mut game.current_position);
+
+        let mut game = Game::new(8);
+        game.current_position.next_move = Some(Color::White);
+        game.insert_piece(Piece::new(game.to_pack(0), Color::White, true));
+        vec![9, 11, 13, 25, 27, 29, 41, 43, 45].iter()
+            .for_each(|pos| game.insert_piece(Piece::new(game.to_pack(*pos), Color::Black, false)));
+        cross_check(&mut game.current_position);
+    }
+
+    #[test]
+    fn random_games_cross_check() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for variant in [Variant::Russian, Variant::International, Variant::Brazilian, Variant::Pool, Variant::English] {
+            let rules = RuleSet::new(variant);
+            let geometry = BitGeometry::new(&rules).unwrap();
+            for _game in 0..30 {
+                let mut pos = Position::start_position(Arc::new(PositionEnvironment::with_rules(rules.clone())));
+                let mut bits = BitPosition::from_position(&geometry, &pos);
+                let mut undo_stack = vec![];
+                for _ply in 0..200 {
+                    cross_check(&mut pos);
+                    let list = bits.get_move_list(&geometry, false);
+                    if list.list.is_empty() { break; }
+                    let mov = &list.list[rng.gen_range(0..list.list.len())];
+                    let before = bits.clone();
+                    let undo = bits.make_move(&geometry, mov);
+                    pos.make_move(mov);
+                    assert_eq!(bits, BitPosition::from_position(&geometry, &pos));
+                    undo_stack.push((before, undo));
+                }
+                while let Some((before, undo)) = undo_stack.pop() {
+                    bits.unmake_move(&undo);
+                    assert_eq!(bits, before);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::color::Color;
+use crate::game::Game;
+use crate::moves_list::MoveItem;
+use crate::notation::NotationError;
+use crate::pdn::PdnGame;
+use crate::position::Position;
+use crate::PositionHistory::FinishType;
+use crate::tuning::result_score;
+
+// plies of a game that go into the book by default
+pub const BOOK_PLIES: usize = 16;
+
+// Move of the book, known by the key of the position it leads to. Results are those of the
+// side that played it, games without a result only count as played.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BookMove {
+    pub key: u64,
+    pub games: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl BookMove {
+    // expected score of the move, a move without results counts as a draw
+    pub fn expected(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0 + 1.0) / ((self.wins + self.draws + self.losses) as f64 + 2.0)
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.games as f64 * self.expected()
+    }
+
+    fn add(&mut self, score: Option<f64>) {
+        self.games += 1;
+        match score {
+            Some(x) if x > 0.5 => self.wins += 1,
+            Some(x) if x < 0.5 => self.losses += 1,
+            Some(_) => self.draws += 1,
+            None => {}
+        }
+    }
+}
+
+// Opening moves keyed by the zobrist key of the position, built from PDN games or
+// self-play and updated with the results of the games played from it
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OpeningBook {
+    pub max_plies: usize,
+    positions: HashMap<u64, Vec<BookMove>>,
+}
+
+impl OpeningBook {
+    pub fn new(max_plies: usize) -> OpeningBook {
+        OpeningBook { max_plies, positions: HashMap::new() }
+    }
+
+    pub fn from_json(json: &str) -> Result<OpeningBook, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn moves(&self, key: u64) -> &[BookMove] {
+        self.positions.get(&key).map_or(&[], |x| x.as_slice())
+    }
+
+    // the first `max_plies` moves from `pos`, `result` is white's score if known
+    pub fn add_line(&mut self, pos: &Position, moves: &[MoveItem], result: Option<f64>) {
+        let mut pos = pos.clone();
+        for move_item in moves.iter().take(self.max_plies) {
+            let key = pos.zobrist();
+            let score = result.map(|x| if pos.next_move == Some(Color::White) { x } else { 1.0 - x });
+            pos.make_move(move_item);
+            let after = pos.zobrist();
+            let moves = self.positions.entry(key).or_default();
+            match moves.iter_mut().find(|x| x.key == after) {
+                Some(book_move) => book_move.add(score),
+                None => {
+                    let mut book_move = BookMove { key: after, ..BookMove::default() };
+                    book_move.add(score);
+                    moves.push(book_move);
+                }
+            }
+        }
+    }
+
+    fn game_line(game: &Game) -> (Position, Vec<MoveItem>) {
+        let moves = game.position_history.borrow().iter().filter_map(|x| x.borrow().mov.clone()).collect();
+        (game.initial_position(), moves)
+    }
+
+    // learning: the moves of a finished game get its result
+    pub fn learn(&mut self, game: &Game, finish: &FinishType) {
+        let (pos, moves) = OpeningBook::game_line(game);
+        self.add_line(&pos, &moves, Some(result_score(finish)));
+    }
+
+    // games of a PDN file, returns the number of games added
+    pub fn add_pdn(&mut self, game: &mut Game, text: &str) -> Result<usize, NotationError> {
+        let games = PdnGame::parse_all(text)?;
+        for pdn in &games {
+            game.load_pdn_game(pdn)?;
+            let result = match pdn.result.as_deref() {
+                Some("2-0" | "1-0") => Some(1.0),
+                Some("0-2" | "0-1") => Some(0.0),
+                Some("1-1") => Some(0.5),
+                _ => None
+            };
+            let (pos, moves) = OpeningBook::game_line(game);
+            self.add_line(&pos, &moves, result);
+        }
+        Ok(games.len())
+    }
+
+    // Book move of `pos`, if any. With a variety of 0 the move
+    // of the highest weight is played, with 1 a move at random in proportion to its weight,
+    // between them the weights are raised to the power 1 / variety.
+    pub fn probe(&self, pos: &Position, variety: f64, rng: &mut impl Rng) -> Option<MoveItem> {
+        let book_moves = self.positions.get(&pos.zobrist())?;
+        let mut pos = pos.clone();
+        let move_list = pos.get_move_list_cached();
+        let list = &move_list.as_ref().as_ref().unwrap().list;
+        // book moves still legal from the position, a collision of keys could bring others
+        let candidates: Vec<_> = list.iter().filter_map(|move_item| {
+            pos.make_move(move_item);
+            let after = pos.zobrist();
+            pos.unmake_move(move_item);
+            let book_move = book_moves.iter().find(|x| x.key == after)?;
+            Some((move_item, book_move.weight()))
+        }).collect();
+        if candidates.is_empty() { return None; }
+        if variety <= 0.0 {
+            return candidates.iter().max_by(|a, b| a.1.total_cmp(&b.1)).map(|x| x.0.clone());
+        }
+        let weights: Vec<f64> = candidates.iter().map(|x| x.1.powf(1.0 / variety.min(1.0))).collect();
+        let mut r = rng.gen::<f64>() * weights.iter().sum::<f64>();
+        for (candidate, weight) in candidates.iter().zip(&weights) {
+            if r < *weight { return Some(candidate.0.clone()); }
+            r -= weight;
+        }
+        candidates.last().map(|x| x.0.clone())
+    }
+
+    // Games of `game` against itself from its current position, opened by `random_plies`
+    // random moves, then book moves played at random and searched to `depth` out of the
+    // book, learned from as they finish. The random moves bring new lines, the results
+    // thin out the bad ones; the random moves themselves are not learned.
+    pub fn self_play(&mut self, game: &Game, games: usize, depth: i16, random_plies: usize, rng: &mut impl Rng) {
+        const MAX_PLIES: usize = 300;
+        for _ in 0..games {
+            let mut play = Game::with_rules(game.position_environment.rules.clone());
+            play.set_eval_params_rust(game.eval_params().clone());
+            play.set_fen(&game.get_fen()).unwrap();
+            let mut finish = None;
+            for ply in 0..MAX_PLIES {
+                let book_move = if ply < random_plies {
+                    let list = play.current_position.get_move_list_cached();
+                    let list = &list.as_ref().as_ref().unwrap().list;
+                    if list.is_empty() { break; }
+                    Some(list[rng.gen_range(0..list.len())].clone())
+                } else if ply < self.max_plies {
+                    self.probe(&play.current_position, 1.0, rng)
+                } else { None };
+                match book_move {
+                    Some(move_item) => play.make_move_by_move_item(&move_item),
+                    None => {
+                        let best = play.best_move(depth, i32::MIN, i32::MAX, 0);
+                        if best.pv.is_empty() { break; }
+                        play.make_move_by_pos_item(&best);
+                    }
+                }
+                finish = play.position_history.borrow_mut().finish_check();
+                if finish.is_some() { break; }
+            }
+            let (mut pos, mut moves) = OpeningBook::game_line(&play);
+            moves.truncate(self.max_plies);
+            let random = random_plies.min(moves.len());
+            moves[..random].iter().for_each(|x| pos.make_move(x));
+            self.add_line(&pos, &moves[random..], Some(finish.map_or(0.5, |x| result_score(&x))));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::book::OpeningBook;
+    use crate::game::Game;
+    use crate::PositionHistory::FinishType;
+
+    const PDN: &str = "[Event \"a\"]\n[Result \"2-0\"]\n1. 22-18 11-15 2. 18x11 8x15 2-0\n\n\
+        [Event \"b\"]\n[Result \"0-2\"]\n1. 22-18 11-16 2. 24-19 0-2\n\n\
+        [Event \"c\"]\n1. 23-19 9-13 *\n";
+
+    fn play(game: &mut Game, text: &str) {
+        let move_item = game.find_move(text).unwrap();
+        game.make_move_by_move_item(&move_item);
+    }
+
+    fn after_22_18() -> String {
+        let mut game = Game::new(8);
+        game.set_start_position();
+        play(&mut game, "22-18");
+        game.get_fen()
+    }
+
+    fn book() -> (Game, OpeningBook) {
+        let mut game = Game::new(8);
+        let mut book = OpeningBook::new(3);
+        assert_eq!(book.add_pdn(&mut game, PDN).unwrap(), 3);
+        game.set_start_position();
+        (game, book)
+    }
+
+    #[test]
+    fn build() {
+        let (mut game, book) = book();
+        // the start, after 22-18 and after 23-19, after 22-18 11-15 and 22-18 11-16
+        assert_eq!(book.len(), 5);
+        let moves = book.moves(game.current_position.zobrist());
+        assert_eq!(moves.len(), 2);
+        // 22-18 won once and lost once, 23-19 has no result
+        let played = moves.iter().find(|x| x.games == 2).unwrap();
+        assert_eq!((played.wins, played.draws, played.losses), (1, 0, 1));
+        let other = moves.iter().find(|x| x.games == 1).unwrap();
+        assert_eq!((other.wins, other.draws, other.losses), (0, 0, 0));
+        // black won after 11-16, the move is black's
+        play(&mut game, "22-18");
+        let replies = book.moves(game.current_position.zobrist());
+        assert_eq!(replies.iter().map(|x| x.wins).sum::<u32>(), 1);
+        assert_eq!(OpeningBook::from_json(&book.to_json()).unwrap(), book);
+        assert!(book.moves(0).is_empty());
+    }
+
+    #[test]
+    fn probe() {
+        let (mut game, book) = book();
+        let mut rng = StdRng::seed_from_u64(3);
+        // 22-18 weighs twice as much as 23-19
+        let first = book.probe(&game.current_position, 0.0, &mut rng).unwrap();
+        game.make_move_by_move_item(&first);
+        assert_eq!(game.get_fen(), after_22_18());
+        // every book move comes up with full variety
+        game.set_start_position();
+        let keys: std::collections::HashSet<_> = (0..50).map(|_| {
+            let move_item = book.probe(&game.current_position, 1.0, &mut rng).unwrap();
+            let mut pos = game.current_position.clone();
+            pos.make_move(&move_item);
+            pos.zobrist()
+        }).collect();
+        assert_eq!(keys.len(), 2);
+        // out of the book
+        play(&mut game, "21-17");
+        assert!(book.probe(&game.current_position, 0.5, &mut rng).is_none());
+    }
+
+    #[test]
+    fn learning() {
+        let (mut game, mut book) = book();
+        let start = game.current_position.zobrist();
+        play(&mut game, "23-19");
+        play(&mut game, "9-13");
+        let before = book.moves(start).iter().find(|x| x.games == 1).unwrap().weight();
+        book.learn(&game, &FinishType::BlackWin);
+        book.learn(&game, &FinishType::BlackWin);
+        let after = book.moves(start).iter().find(|x| x.games == 3).unwrap();
+        assert_eq!(after.losses, 2);
+        assert!(after.weight() / 3.0 < before);
+        // self-play adds its games
+        let mut book = OpeningBook::new(4);
+        game.set_start_position();
+        book.self_play(&game, 2, 1, 0, &mut StdRng::seed_from_u64(5));
+        let played: u32 = book.moves(start).iter().map(|x| x.games).sum();
+        assert_eq!(played, 2);
+        // but not its random moves
+        let mut book = OpeningBook::new(4);
+        book.self_play(&game, 2, 1, 1, &mut StdRng::seed_from_u64(5));
+        assert!(book.moves(start).is_empty());
+        let played: u32 = book.positions.values().flatten().map(|x| x.games).sum();
+        assert_eq!(played, 2 * 3);
+    }
+
+    #[test]
+    fn game_book() {
+        let (mut game, book) = book();
+        game.set_book(Some(book));
+        game.set_book_variety(0.0);
+        let best = game.book_move().unwrap();
+        assert_eq!(best.pv.len(), 1);
+        game.make_move_by_pos_item(&best);
+        assert_eq!(game.get_fen(), after_22_18());
+        assert!(game.book_move().is_some());
+        game.set_book_variety(1.0);
+        play(&mut game, "9-13");
+        assert!(game.book_move().is_none());
+        // learning from the finished game
+        assert!(!game.book_learn());
+        game.set_fen("W:WK22:B18").unwrap();
+        play(&mut game, "22x15");
+        let key = game.initial_position().zobrist();
+        assert!(game.book_learn());
+        // a game is learned once
+        assert!(!game.book_learn());
+        assert_eq!(game.book().unwrap().moves(key)[0].games, 1);
+        game.set_fen("W:WK22:B18").unwrap();
+        play(&mut game, "22x15");
+        assert!(game.book_learn());
+        assert_eq!(game.book().unwrap().moves(key)[0].games, 2);
+    }
+}
@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use crate::color::Color;
+use crate::piece::Piece;
+use crate::position::PieceCount;
+use crate::position_environment::PositionEnvironment;
+
+// number of weights, in the order of EvalParams::weights
+pub const WEIGHTS: usize = 8;
+// bound of the evaluations, far below the scores of won positions whatever the weights
+pub const MAX_EVAL: i64 = 1 << 26;
+
+// Weights of Position::evaluate, in thousandths of a man by default. Missing fields of
+// the JSON form keep their defaults, so a personality only lists what it changes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(default)]
+pub struct EvalParams {
+    pub man: i32,
+    pub king: i32,
+    // per empty square a piece can reach along its directions
+    pub mobility: i32,
+    // per row a man has advanced from its first row
+    pub advancement: i32,
+    // per piece on the central squares
+    pub center: i32,
+    // per man left on its first row, guarding it against promotions
+    pub back_rank: i32,
+    // for the side to move
+    pub tempo: i32,
+    // per unit of the cell grade: forward directions for men, reach for kings
+    pub cell_grade: i32,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        EvalParams { man: 1000, king: 3000, mobility: 1, advancement: 0, center: 0, back_rank: 0, tempo: 0, cell_grade: 0 }
+    }
+}
+
+impl EvalParams {
+    pub fn weights(&self) -> [i32; WEIGHTS] {
+        [self.man, self.king, self.mobility, self.advancement, self.center, self.back_rank, self.tempo, self.cell_grade]
+    }
+
+    pub fn from_weights(w: [i32; WEIGHTS]) -> EvalParams {
+        EvalParams {
+            man: w[0],
+            king: w[1],
+            mobility: w[2],
+            advancement: w[3],
+            center: w[4],
+            back_rank: w[5],
+            tempo: w[6],
+            cell_grade: w[7],
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<EvalParams, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    // white material minus black material
+    pub fn material(&self, white: &PieceCount, black: &PieceCount) -> i64 {
+        (white.simple as i64 - black.simple as i64) * self.man as i64
+            + (white.king as i64 - black.king as i64) * self.king as i64
+    }
+
+    // positional score of a piece at `pos` for its own side
+    pub fn piece(&self, piece: &Piece, pos: usize, environment: &PositionEnvironment) -> i64 {
+        let size = environment.size as usize;
+        let row = pos / (size / 2);
+        // rows from the piece's own first row
+        let advanced = if piece.color == Color::White { row } else { size - 1 - row };
+        let col = environment.pack_to_board[pos] % size;
+        let central = |x: usize| x >= size / 4 && x < size - size / 4;
+        let mut score = environment.cell_grade[pos].get(piece) as i64 * self.cell_grade as i64;
+        if central(row) && central(col) { score += self.center as i64; }
+        if !piece.is_king {
+            score += advanced as i64 * self.advancement as i64;
+            if advanced == 0 { score += self.back_rank as i64; }
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::{EvalParams, MAX_EVAL};
+    use crate::game::Game;
+
+    fn evaluate(fen: &str, params: EvalParams) -> i32 {
+        let mut game = Game::new(8);
+        game.set_eval_params_rust(params);
+        game.set_fen(fen).unwrap();
+        game.current_position.evaluate()
+    }
+
+    #[test]
+    fn defaults() {
+        // the values of the fixed material and mobility evaluation
+        for (fen, eval) in [("W:W21-32:B1-12", 0), ("B:W18,21,22,24,25,26,27,29,30,31:B1,2,3,5,6,7,9,10,11,13", 2),
+                            ("W:WK5,22,26,30:B9,13,15,16,K27", -1011)] {
+            assert_eq!(evaluate(fen, EvalParams::default()), eval, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn json() {
+        let params = EvalParams::from_json(r#"{"king": 2500, "advancement": 20}"#).unwrap();
+        assert_eq!(params, EvalParams { king: 2500, advancement: 20, ..EvalParams::default() });
+        assert_eq!(EvalParams::from_json(&params.to_json()).unwrap(), params);
+        assert!(EvalParams::from_json(r#"{"king": "many"}"#).is_err());
+    }
+
+    #[test]
+    fn weights() {
+        let zero = EvalParams { man: 0, king: 0, mobility: 0, ..EvalParams::default() };
+        // e5 has advanced 4 rows, d6 2 rows for black
+        assert_eq!(evaluate("W:W15:B10", EvalParams { advancement: 10, ..zero.clone() }), 20);
+        // h8 is black's first row
+        assert_eq!(evaluate("W:W23:B4,10", EvalParams { back_rank: 7, ..zero.clone() }), -7);
+        // d4 and e5 are central, a1 and h8 are not
+        assert_eq!(evaluate("W:W18,29:B4,15", EvalParams { center: 5, ..zero.clone() }), 0);
+        assert_eq!(evaluate("W:W18,29:B4", EvalParams { center: 5, ..zero.clone() }), 5);
+        assert_eq!(evaluate("B:W18:B4", EvalParams { tempo: 3, ..zero.clone() }), -3);
+        // kings reach 8 squares from a1 and h8, 15 from c3 and 9 from b8
+        assert_eq!(evaluate("W:WK29:BK4", EvalParams { cell_grade: 1, ..zero.clone() }), 0);
+        assert_eq!(evaluate("W:WK22:BK1", EvalParams { cell_grade: 1, ..zero.clone() }), 6);
+        assert_eq!(evaluate("W:WK22:B1", EvalParams { king: 5000, ..EvalParams::default() })
+                       - evaluate("W:WK22:B1", EvalParams::default()), 2000);
+    }
+
+    #[test]
+    fn game_params() {
+        let mut game = Game::new(8);
+        game.set_fen("W:W21-32:B1-12").unwrap();
+        game.best_move(3, i32::MIN, i32::MAX, 0);
+        let eval = game.current_position.evaluate();
+        // the cached evaluation and the table go with the old weights
+        game.set_eval_params_rust(EvalParams { tempo: 50, ..EvalParams::default() });
+        assert_eq!(game.eval_params().tempo, 50);
+        assert_eq!(game.current_position.evaluate(), eval + 50);
+        assert!(game.tt.probe(game.zobrist()).is_none());
+        // weights survive loading a game of another variant
+        game.load_pdn("[GameType \"20\"]\n1. 32-28 *").unwrap();
+        assert_eq!(game.eval_params().tempo, 50);
+    }
+
+    #[test]
+    fn extreme_weights() {
+        let params = EvalParams::from_json(&format!(r#"{{"man": {}, "mobility": {}}}"#, i32::MIN, i32::MIN)).unwrap();
+        assert_eq!(evaluate("W:WK5,22,26,30:B9,13,15,16,K27", params.clone()), MAX_EVAL as i32);
+        // still a loss for the side without moves
+        assert!(evaluate("W:W1:B5", params) < i32::MIN / 4);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::color::Color;
+use crate::moves_list::MoveItem;
+use crate::position::Position;
+
+// Input planes of the networks, each a size x size grid of the board from a1 on, light
+// squares left at 0: white men, white kings, black men, black kings, then a plane of ones
+// when white is to move.
+pub const PLANES: usize = 5;
+const SIDE_TO_MOVE: usize = 4;
+
+pub fn input_len(size: usize) -> usize {
+    PLANES * size * size
+}
+
+pub fn plane(color: Color, is_king: bool) -> usize {
+    (if color == Color::White { 0 } else { 2 }) + is_king as usize
+}
+
+pub fn encode(pos: &Position) -> Vec<f32> {
+    let env = &pos.environment;
+    let area = (env.size as usize).pow(2);
+    let mut input = vec![0.0; PLANES * area];
+    for piece in pos.cells.iter().flatten() {
+        input[plane(piece.color, piece.is_king) * area + env.pack_to_board[piece.pos]] = 1.0;
+    }
+    if pos.next_move == Some(Color::White) {
+        input[SIDE_TO_MOVE * area..].fill(1.0);
+    }
+    input
+}
+
+// policy output of a move: its squares, packed, as from * squares + to
+pub fn policy_index(pos: &Position, move_item: &MoveItem) -> usize {
+    let mov = move_item.get_chain_piece_move();
+    mov.from() * pos.cells.len() + mov.to()
+}
+
+pub fn policy_len(size: usize) -> usize {
+    (size * size / 2).pow(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+    use crate::features::{encode, input_len, plane, policy_index, policy_len, PLANES};
+    use crate::game::Game;
+
+    #[test]
+    fn planes() {
+        let mut game = Game::new(8);
+        game.set_fen("W:WK1,21:B12").unwrap();
+        let input = encode(&game.current_position);
+        assert_eq!(input.len(), input_len(8));
+        let area = 64;
+        let count = |plane: usize| input[plane * area..(plane + 1) * area].iter().filter(|x| **x == 1.0).count();
+        assert_eq!([count(0), count(1), count(2), count(3), count(4)], [1, 1, 1, 0, 64]);
+        // square 1 is b8, 21 is a3, 12 is h6
+        assert_eq!(input[plane(Color::White, true) * area + 57], 1.0);
+        assert_eq!(input[plane(Color::White, false) * area + 16], 1.0);
+        assert_eq!(input[plane(Color::Black, false) * area + 47], 1.0);
+        game.set_fen("B:WK1,21:B12").unwrap();
+        assert!(encode(&game.current_position)[(PLANES - 1) * area..].iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn policy() {
+        let mut game = Game::new(8);
+        game.set_start_position();
+        let list = game.current_position.get_move_list(false).list;
+        let mut indices: Vec<_> = list.iter().map(|x| policy_index(&game.current_position, x)).collect();
+        assert!(indices.iter().all(|x| *x < policy_len(8)));
+        indices.sort();
+        indices.dedup();
+        assert_eq!(indices.len(), list.len());
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use crate::color::Color;
+use crate::notation::{Notation, NotationError};
+use crate::piece::Piece;
+use crate::position::Position;
+use crate::position_environment::PositionEnvironment;
+
+// PDN FEN: "W:W21,22,K5:B1,2,3" - side to move, then white and black piece lists,
+// kings prefixed with K, ranges like 1-12 and algebraic squares accepted on input
+impl Position {
+    pub fn from_fen(environment: Arc<PositionEnvironment>, fen: &str) -> Result<Position, NotationError> {
+        let mut pos = Position::new(environment);
+        let fen = fen.trim().trim_end_matches('.');
+        let mut parts = fen.split(':');
+        pos.next_move = Some(parse_color(parts.next().unwrap_or(""))?);
+        for part in parts {
+            let part = part.trim();
+            if part.is_empty() { continue; }
+            let first = part.chars().next().unwrap();
+            let color = parse_color(&part[..first.len_utf8()])?;
+            for item in part[first.len_utf8()..].split(',') {
+                let item = item.trim();
+                if item.is_empty() { continue; }
+                let (is_king, squares) = match item.strip_prefix(['K', 'k']) {
+                    Some(rest) => (true, rest),
+                    None => (false, item)
+                };
+                for square in pos.parse_squares(squares)? {
+                    if pos.cells[square].is_some() {
+                        return Err(NotationError(format!("square '{}' is set twice", item)));
+                    }
+                    pos.insert_piece(Piece::new(square, color, is_king));
+                }
+            }
+        }
+        Ok(pos)
+    }
+
+    fn parse_squares(&self, squares: &str) -> Result<Vec<usize>, NotationError> {
+        if let Some((a, b)) = squares.split_once('-') {
+            let range = |x: &str| x.trim().parse::<usize>()
+                .map_err(|_| NotationError(format!("invalid range '{}'", squares)));
+            let (a, b) = (range(a)?, range(b)?);
+            if a > b { return Err(NotationError(format!("invalid range '{}'", squares))); }
+            return (a..=b).map(|n| self.environment.parse_square(&n.to_string())).collect();
+        }
+        Ok(vec![self.environment.parse_square(squares)?])
+    }
+
+    pub fn to_fen(&self) -> String {
+        self.to_fen_with(Notation::Numeric)
+    }
+
+    pub fn to_fen_with(&self, notation: Notation) -> String {
+        let color = |color: Color| if color == Color::White { "W" } else { "B" };
+        let mut fen = String::from(self.next_move.map_or("?", color));
+        for side in [Color::White, Color::Black] {
+            let mut pieces: Vec<_> = self.cells.iter().flatten()
+                .filter(|x| x.color == side)
+                .map(|x| (self.environment.pack_to_numeric(x.pos), x))
+                .collect();
+            pieces.sort_by_key(|x| x.0);
+            let list: Vec<_> = pieces.iter()
+                .map(|(_, x)| format!("{}{}", if x.is_king { "K" } else { "" },
+                                      self.environment.square_to_string(x.pos, notation)))
+                .collect();
+            fen += &format!(":{}{}", color(side), list.join(","));
+        }
+        fen
+    }
+}
+
+fn parse_color(s: &str) -> Result<Color, NotationError> {
+    match s.trim() {
+        "W" | "w" => Ok(Color::White),
+        "B" | "b" => Ok(Color::Black),
+        x => Err(NotationError(format!("invalid color '{}'", x)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::color::Color;
+    use crate::notation::Notation;
+    use crate::position::Position;
+    use crate::position_environment::PositionEnvironment;
+
+    #[test]
+    fn fen_round_trip() {
+        let env = Arc::new(PositionEnvironment::new(8));
+        let pos = Position::from_fen(env.clone(), "W:W21,22,K5:B1,2,3").unwrap();
+        assert_eq!(pos.next_move, Some(Color::White));
+        assert_eq!(pos.state.white.simple, 2);
+        assert_eq!(pos.state.white.king, 1);
+        assert_eq!(pos.state.black.simple, 3);
+        assert!(pos.cells[env.numeric_to_pack(5).unwrap()].as_ref().unwrap().is_king);
+        assert_eq!(pos.to_fen(), "W:WK5,21,22:B1,2,3");
+        assert_eq!(Position::from_fen(env.clone(), &pos.to_fen()).unwrap(), pos);
+        let algebraic = pos.to_fen_with(Notation::Algebraic);
+        assert_eq!(Position::from_fen(env, &algebraic).unwrap(), pos);
+    }
+
+    #[test]
+    fn fen_start_position() {
+        for size in [8, 10] {
+            let env = Arc::new(PositionEnvironment::new(size));
+            let start = Position::start_position(env.clone());
+            let n = start.cells.len();
+            let rows = (size as usize / 2 - 1) * size as usize / 2;
+            let fen = format!("W:W{}-{}:B1-{}.", n - rows + 1, n, rows);
+            assert_eq!(Position::from_fen(env, &fen).unwrap(), start);
+        }
+    }
+
+    #[test]
+    fn fen_errors() {
+        let env = Arc::new(PositionEnvironment::new(8));
+        assert!(Position::from_fen(env.clone(), "X:W1").is_err());
+        assert!(Position::from_fen(env.clone(), "W:W1,1").is_err());
+        assert!(Position::from_fen(env.clone(), "W:W33").is_err());
+        assert!(Position::from_fen(env.clone(), "W:Wb1").is_err());
+        assert!(Position::from_fen(env.clone(), "W:é21:B1").is_err());
+        assert!(Position::from_fen(env, "W:W5-1").is_err());
+    }
+}
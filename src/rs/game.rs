@@ -3,6 +3,7 @@ use std::cmp::{min, Ordering};
 use std::io;
 use std::io::Write;
 use std::rc::Rc;
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 use crate::color::Color;
 use crate::moves::BoardPos;
@@ -16,7 +17,18 @@ use crate::color::Color::{Black, White};
 use crate::PositionHistory::FinishType::{BlackWin, Draw1, Draw2, Draw3, Draw4, Draw5, WhiteWin};
 use crate::log;
 use rand::prelude::*;
-use crate::mcts::McTree;
+use crate::mcts::{McTree, MctsConfig};
+use crate::notation::Notation;
+use crate::pdn::PdnGame;
+use crate::rules::{RuleSet, Variant};
+use crate::transposition::TranspositionTable;
+use crate::negamax::{Searcher, INFINITY};
+use crate::eval::EvalParams;
+use crate::ordering::MoveOrdering;
+use crate::tablebase::Tablebase;
+use crate::nn::Network;
+use crate::book::OpeningBook;
+use crate::search::{InfoCallback, QuiescenceConfig, SearchControl, SearchLimits};
 use crate::PositionHistory::{FinishType, PositionAndMove, PositionHistory};
 
 #[wasm_bindgen]
@@ -25,42 +37,100 @@ use crate::PositionHistory::{FinishType, PositionAndMove, PositionHistory};
 #[ts(export)]
 pub struct BestPos {
     pos: Option<Rc<RefCell<PositionAndMove>>>,
-    deep_eval: i32,
+    pub(crate) deep_eval: i32,
+    // principal variation: the move of `pos` and the expected replies
+    #[wasm_bindgen(skip)]
+    pub pv: Vec<MoveItem>,
 }
 
 impl BestPos {
+    fn new(pos: Option<Rc<RefCell<PositionAndMove>>>, deep_eval: i32) -> BestPos {
+        BestPos { pos, deep_eval, pv: vec![] }
+    }
+
+    // the move of `pos` followed by the line found below it
+    fn with_line(pos: Rc<RefCell<PositionAndMove>>, deep_eval: i32, line: Vec<MoveItem>) -> BestPos {
+        let mut pv = Vec::with_capacity(line.len() + 1);
+        pv.push(pos.borrow().mov.clone().unwrap());
+        pv.extend(line);
+        BestPos { pos: Some(pos), deep_eval, pv }
+    }
+
+    pub fn pv_text(&self, environment: &PositionEnvironment, notation: Notation) -> String {
+        self.pv.iter().map(|x| x.text(environment, notation).to_string()).collect::<Vec<_>>().join(" ")
+    }
+
     pub fn get_move_item(&self) -> MoveItem {
         self.pos.as_ref().unwrap().borrow().mov.as_ref().unwrap().clone()
     }
 }
 
 
+const TT_SIZE_MB: usize = 8;
+
 #[wasm_bindgen]
 pub struct Game {
     #[wasm_bindgen(skip)]
     pub position_history: Rc<RefCell<PositionHistory>>,
-    position_environment: Rc<PositionEnvironment>,
+    pub(crate) position_environment: Arc<PositionEnvironment>,
     #[wasm_bindgen(skip)]
     pub current_position: Position,
     max_depth: i16,
     #[wasm_bindgen(skip)]
     pub tree: Option<McTree>,
+    pub(crate) pdn_tags: Vec<(String, String)>,
+    pub(crate) tt: TranspositionTable,
+    pub(crate) search: SearchControl,
+    pub(crate) search_limits: SearchLimits,
+    pub(crate) on_info: Option<InfoCallback>,
+    pub(crate) quiescence: QuiescenceConfig,
+    pub(crate) ordering: MoveOrdering,
+    pub(crate) tablebase: Option<Arc<Tablebase>>,
+    pub(crate) book: Option<OpeningBook>,
+    // 0 plays the heaviest book move, 1 picks them in proportion to their weights
+    pub(crate) book_variety: f64,
+    // the finished game went into the book already
+    pub(crate) book_learned: bool,
+    pub(crate) mcts_config: MctsConfig,
+    // evaluation of the alpha-beta leaves instead of the static one
+    pub(crate) network: Option<Arc<Network>>,
+    // threads and tables of the root splitting workers
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) pool: Option<rayon::ThreadPool>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) worker_tts: Vec<TranspositionTable>,
 }
 
 #[wasm_bindgen]
 impl Game {
     #[wasm_bindgen(constructor)]
     pub fn new(size: i8) -> Self {
-        let environment = Rc::new(PositionEnvironment::new(size));
-        let position = Position::new(environment.clone());
-        let position_history = Rc::new(RefCell::new(PositionHistory::new()));
-        Game {
-            position_environment: environment.clone(),
-            current_position: position.clone(),
-            position_history,
-            max_depth: 3,
-            tree: None,
-        }
+        Game::with_rules(RuleSet::russian(size))
+    }
+
+    // empty board of the variant, set it up with set_start_position or set_fen
+    #[wasm_bindgen]
+    pub fn with_variant(variant: Variant) -> Game {
+        Game::with_rules(RuleSet::new(variant))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn variant(&self) -> Variant {
+        self.position_environment.rules.variant
+    }
+
+    // Zobrist key of the current position, for caches outside the engine
+    #[wasm_bindgen(getter)]
+    pub fn zobrist(&self) -> u64 {
+        self.current_position.zobrist()
+    }
+
+    #[wasm_bindgen]
+    pub fn set_start_position(&mut self) {
+        self.current_position = Position::start_position(self.position_environment.clone());
+        self.position_history.borrow_mut().cut_to(0);
+        self.tree = None;
+        self.book_learned = false;
     }
 
     #[wasm_bindgen]
@@ -69,6 +139,88 @@ impl Game {
     }
 
 
+    // transposition table size in megabytes, its content is dropped
+    #[wasm_bindgen]
+    pub fn set_tt_size(&mut self, size_mb: usize) {
+        self.tt = TranspositionTable::new(size_mb);
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_tt(&mut self) {
+        self.tt.clear();
+    }
+
+    // evaluation weights as JSON, fields left out keep their default values
+    #[wasm_bindgen]
+    pub fn set_eval_params(&mut self, json: &str) -> Result<(), JsValue> {
+        let params = EvalParams::from_json(json).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.set_eval_params_rust(params);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_eval_params(&self) -> String {
+        self.eval_params().to_json()
+    }
+
+    // endgame tables in the format of Tablebase::write
+    #[wasm_bindgen]
+    pub fn load_tablebase(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let tablebase = Tablebase::read(&mut &bytes[..]).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.set_tablebase(Some(Arc::new(tablebase)));
+        Ok(())
+    }
+
+    // network in the format of Network::write, for a board of the size of the game
+    #[wasm_bindgen]
+    pub fn load_network(&mut self, bytes: &[u8]) -> Result<(), JsValue> {
+        let network = Network::read(&mut &bytes[..]).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        if network.size() != self.position_environment.size as usize {
+            return Err(JsValue::from_str("network of another board size"));
+        }
+        self.set_network(Some(Arc::new(network)));
+        Ok(())
+    }
+
+    // opening book in the JSON form of OpeningBook
+    #[wasm_bindgen]
+    pub fn load_book(&mut self, json: &str) -> Result<(), JsValue> {
+        let book = OpeningBook::from_json(json).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.set_book(Some(book));
+        Ok(())
+    }
+
+    // the book with what it learned, to be saved
+    #[wasm_bindgen]
+    pub fn get_book(&self) -> Option<String> {
+        self.book.as_ref().map(|x| x.to_json())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_book_variety(&mut self, variety: f64) {
+        self.book_variety = variety.clamp(0.0, 1.0);
+    }
+
+    // the moves of a finished game go into the book with its result, once; false if the
+    // game goes on, was learned already or there is no book
+    #[wasm_bindgen]
+    pub fn book_learn(&mut self) -> bool {
+        if self.book_learned { return false; }
+        let finish = self.position_history.borrow_mut().finish_check();
+        match (finish, self.book.take()) {
+            (Some(finish), Some(mut book)) => {
+                book.learn(self, &finish);
+                self.book = Some(book);
+                self.book_learned = true;
+                true
+            }
+            (_, book) => {
+                self.book = book;
+                false
+            }
+        }
+    }
+
     #[wasm_bindgen]
     pub fn insert_piece(&mut self, piece: Piece) {
         self.current_position.insert_piece(piece);
@@ -80,6 +232,85 @@ impl Game {
     }
 
 
+    // replaces the board and side to move, the game history starts over
+    #[wasm_bindgen]
+    pub fn set_fen(&mut self, fen: &str) -> Result<(), JsValue> {
+        let position = Position::from_fen(self.position_environment.clone(), fen)
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.current_position = position;
+        self.position_history.borrow_mut().cut_to(0);
+        self.tree = None;
+        self.book_learned = false;
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_fen(&self) -> String {
+        self.current_position.to_fen()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_fen_with(&self, notation: Notation) -> String {
+        self.current_position.to_fen_with(notation)
+    }
+
+    #[wasm_bindgen]
+    pub fn to_pdn(&self, notation: Notation) -> String {
+        self.to_pdn_game(notation).to_string()
+    }
+
+    // replays the game, stops at the first illegal move
+    #[wasm_bindgen]
+    pub fn load_pdn(&mut self, pdn: &str) -> Result<(), JsValue> {
+        PdnGame::parse(pdn)
+            .and_then(|pdn| self.load_pdn_game(&pdn))
+            .map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    // legal moves as text, every strike path listed
+    #[wasm_bindgen]
+    pub fn get_move_list_text(&mut self, notation: Notation) -> JsValue {
+        let move_list = self.get_move_list(true);
+        let list: Vec<_> = move_list.list.iter()
+            .map(|x| x.text(&self.position_environment, notation).to_string()).collect();
+        match serde_wasm_bindgen::to_value(&list) {
+            Ok(js) => js,
+            Err(_err) => JsValue::UNDEFINED,
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn move_to_text(&self, move_item: JsValue, notation: Notation) -> Result<String, JsValue> {
+        let move_item: MoveItem = serde_wasm_bindgen::from_value(move_item)?;
+        Ok(move_item.text(&self.position_environment, notation).to_string())
+    }
+
+    #[wasm_bindgen]
+    pub fn parse_move(&mut self, text: &str) -> Result<JsValue, JsValue> {
+        let move_item = self.find_move(text).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        Ok(serde_wasm_bindgen::to_value(&move_item)?)
+    }
+
+    // returns true or the finish type like move_by_index_ts_n, errors on illegal or ambiguous text
+    #[wasm_bindgen]
+    pub fn make_move_by_text(&mut self, text: &str) -> Result<JsValue, JsValue> {
+        let move_item = self.find_move(text).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.make_move_by_move_item(&move_item);
+        let finish = self.position_history.borrow_mut().finish_check();
+        match finish {
+            Some(finish) => Ok(serde_wasm_bindgen::to_value(&finish)?),
+            None => Ok(JsValue::TRUE)
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn set_pdn_tag(&mut self, name: &str, value: &str) {
+        match self.pdn_tags.iter_mut().find(|x| x.0 == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.pdn_tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
     #[wasm_bindgen(getter)]
     pub fn position(&self) -> JsValue {
         match serde_wasm_bindgen::to_value(&self.current_position) {
@@ -89,97 +320,34 @@ impl Game {
     }
 
     pub fn make_move_by_pos_item(&mut self, pos: &BestPos) {
+        if self.tree.as_mut().is_some_and(|x| !x.advance(&pos.get_move_item())) { self.tree = None; }
         self.current_position.make_move(&mut pos.get_move_item());
         self.position_history.borrow_mut().push(PositionAndMove::from(self.current_position.clone(), pos.get_move_item()));
     }
 
     pub fn make_move_by_move_item(&mut self, move_item: &MoveItem) {
+        if self.tree.as_mut().is_some_and(|x| !x.advance(move_item)) { self.tree = None; }
         self.current_position.make_move(move_item);
         self.position_history.borrow_mut()
             .push(PositionAndMove::from(self.current_position.clone(), move_item.clone()));
     }
 
+    // `best_white` and `best_black` are the window of the search in white's terms,
+    // `max_depth - depth + 1` plies are searched
     #[wasm_bindgen]
-    pub fn best_move(&mut self, mut max_depth: i16, mut best_white: i32,
-                     mut best_black: i32, depth: i16) -> BestPos {
-        // log(&format!("{:?}", self.current_position));
-        let ref move_list = self.current_position.get_move_list_cached();
-        let mut pos_list: Vec<_> = {
-            move_list.as_ref().as_ref().unwrap().list.iter().map(|x| {
-                let mut pos = self.current_position.make_move_and_get_position(x);
-                pos.pos.evaluate();
-                self.current_position.unmake_move(x);
-                pos
-            }).collect()
-        };
-        if pos_list.len() == 0 { panic!("Best move: it`s standoff position") }
-        let move_color = self.current_position.next_move.unwrap();
-        if pos_list.len() < 3 { max_depth += 1; }
-        // if pos_list.len() < 3 { max_depth += 1; } else {
-        //     let mut rng = rand::thread_rng();
-        //     let y: f64 = rng.gen();
-        //     if depth > 3 && (depth % 2 == 0) && y < 10.0 / (depth as f64) {
-        //         let x0: i32 = rng.gen();
-        //         let x1: i32 = rng.gen();
-        //         pos_list.sort_by(|a, b| Ord::cmp(&x0,&x1));
-        //         pos_list = pos_list[0..min(pos_list.len(),4)].to_owned();
-        //         max_depth += 1;
-        //     }
-        // }
-        pos_list.sort_by_key(|x|
-            x.pos.eval.unwrap() * if move_color == White { -1 } else { 1 });
-
-        let mut best_pos = BestPos { pos: None, deep_eval: if move_color == White { i32::MIN } else { i32::MAX } };
-        if depth < max_depth {
-            for pos_it in pos_list {
-                self.current_position.make_move(&pos_it.mov.as_ref().unwrap());
-                self.position_history.borrow_mut().push(pos_it);
-                let finish = self.position_history.borrow_mut().finish_check();
-                if finish.is_some() {
-                    // print!("{:?} {}\n", finish, depth);
-                    // pos_it.position.print_pos();
-                    let mut pos_it = self.position_history.borrow_mut().pop().unwrap();
-                    self.current_position.unmake_move(&pos_it.borrow().mov.as_ref().unwrap());
-                    let eval = pos_it.borrow_mut().pos.evaluate();
-                    return BestPos { deep_eval: eval, pos: Option::from(pos_it) };
-                }
-                let deep_eval =
-                    self.best_move(max_depth, best_white, best_black, depth + 1).deep_eval;
-                let mut pos_it = self.position_history.borrow_mut().pop().unwrap();
-                self.current_position.took_pieces = pos_it.borrow().pos.took_pieces.clone();
-                self.current_position.unmake_move(&pos_it.borrow().mov.as_ref().unwrap());
-                let white = self.current_position.state.white.clone();
-                let black = self.current_position.state.black.clone();
-                self.current_position.state = pos_it.borrow().pos.state.clone();
-                self.current_position.state.white = white;
-                self.current_position.state.black = black;
-                if move_color == White {
-                    if best_black < deep_eval {
-                        // print!("cut at white move depth: {} {} {} {}\n", depth, best_black, best_white, deep_eval);
-                        return BestPos { pos: Some(pos_it), deep_eval };
-                    }
-                    if best_white < deep_eval { best_white = deep_eval }
-                    if best_pos.deep_eval < deep_eval {
-                        best_pos = BestPos { pos: Option::from(pos_it), deep_eval };
-                    }
-                } else {
-                    if best_white > deep_eval {
-                        // print!("cut at black move depth: {} {} {} {}\n", depth, best_black, best_white, deep_eval);
-                        return BestPos { pos: Option::from(pos_it), deep_eval };
-                    }
-                    if best_black > deep_eval { best_black = deep_eval }
-                    if best_pos.deep_eval > deep_eval {
-                        best_pos = BestPos { pos: Option::from(pos_it), deep_eval };
-                    }
-                }
-            }
+    pub fn best_move(&mut self, max_depth: i16, best_white: i32, best_black: i32, depth: i16) -> BestPos {
+        let sign = self.sign();
+        let (alpha, beta) = if sign == 1 {
+            (best_white, best_black)
         } else {
-            for mut pos in pos_list {
-                let eval = pos.pos.evaluate();
-                best_pos = BestPos { deep_eval: eval, pos: Some(Rc::from(RefCell::from(pos))) }
-            }
-        }
-        best_pos
+            (best_black.saturating_neg(), best_white.saturating_neg())
+        };
+        if depth == 0 { self.ordering.new_search(); }
+        let mut searcher = self.searcher();
+        let score = searcher.root(max_depth - depth + 1,
+                                  alpha.clamp(-INFINITY, INFINITY), beta.clamp(-INFINITY, INFINITY));
+        let pv = searcher.pv();
+        self.best_pos(score * sign, pv)
     }
 
     #[wasm_bindgen]
@@ -191,13 +359,44 @@ impl Game {
                 Err(_err) => JsValue::UNDEFINED
             };
         }
-        match serde_wasm_bindgen::to_value(
-            &self.best_move(self.max_depth, i32::MIN, i32::MAX, 0)) {
+        let best = self.book_move().unwrap_or_else(|| self.best_move(self.max_depth, i32::MIN, i32::MAX, 0));
+        match serde_wasm_bindgen::to_value(&best) {
             Ok(js) => js,
             Err(_err) => JsValue::UNDEFINED
         }
     }
 
+    // Monte Carlo tree search with the MCTS config, the tree of the previous moves is kept
+    #[wasm_bindgen]
+    pub fn get_best_move_mcts(&mut self) -> JsValue {
+        let finish = self.position_history.borrow_mut().finish_check();
+        let res = match finish {
+            Some(finish) => serde_wasm_bindgen::to_value(&finish),
+            None => serde_wasm_bindgen::to_value(&self.best_move_mcts())
+        };
+        res.unwrap_or(JsValue::UNDEFINED)
+    }
+
+    // MctsConfig as JSON, fields left out keep their default values
+    #[wasm_bindgen]
+    pub fn set_mcts_config(&mut self, json: &str) -> Result<(), JsValue> {
+        let config = MctsConfig::from_json(json).map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.set_mcts_config_rust(config);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_mcts_config(&self) -> String {
+        self.mcts_config.to_json()
+    }
+
+    // multi-PV: the best `count` root moves with their scores and lines
+    #[wasm_bindgen]
+    pub fn get_best_moves(&mut self, count: usize) -> JsValue {
+        let best_moves = self.best_moves(self.max_depth, count);
+        serde_wasm_bindgen::to_value(&best_moves).unwrap_or(JsValue::UNDEFINED)
+    }
+
     #[wasm_bindgen]
     pub fn make_best_move(&mut self, pos: &BestPos) {
         // log(&format!("{:?}", pos));
@@ -381,8 +580,159 @@ impl Game {
     }
 }
 
+impl Game {
+    // 1 if white is to move, -1 for black: turns scores of the side to move into white's
+    pub(crate) fn sign(&self) -> i32 {
+        if self.current_position.next_move == Some(White) { 1 } else { -1 }
+    }
+
+    // keys of the positions of the game, for repetitions in the search
+    pub(crate) fn history_keys(&self) -> Vec<u64> {
+        self.position_history.borrow().iter().map(|x| x.borrow().pos.zobrist()).collect()
+    }
+
+    pub(crate) fn searcher(&mut self) -> Searcher<'_> {
+        let path = self.history_keys();
+        Searcher::new(&mut self.current_position, &mut self.tt, &mut self.ordering, &mut self.search,
+                      &self.quiescence, path).with_tablebase(self.tablebase.as_deref())
+            .with_network(self.network.as_deref())
+            .with_quiet_moves(self.position_history.borrow().quiet_moves())
+    }
+
+    // the first move of `pv` played from the current position, scored by `deep_eval`
+    pub(crate) fn best_pos(&mut self, deep_eval: i32, mut pv: Vec<MoveItem>) -> BestPos {
+        if pv.is_empty() { return BestPos::new(None, deep_eval); }
+        let line = pv.split_off(1);
+        let pos_it = self.current_position.make_move_and_get_position(&pv[0]);
+        self.current_position.unmake_move(&pv[0]);
+        BestPos::with_line(Rc::new(RefCell::new(pos_it)), deep_eval, line)
+    }
+
+    // Every root move searched with the full window, the best `count` of them
+    // with their own scores and lines, best first.
+    pub fn best_moves(&mut self, max_depth: i16, count: usize) -> Vec<BestPos> {
+        let sign = self.sign();
+        self.ordering.new_search();
+        let root_moves = self.searcher().root_moves(max_depth + 1);
+        let mut res: Vec<_> = root_moves.into_iter()
+            .map(|(score, pv)| self.best_pos(score * sign, pv)).collect();
+        res.sort_by_key(|x| -x.deep_eval * sign);
+        res.truncate(count);
+        res
+    }
+
+    pub fn eval_params(&self) -> &EvalParams {
+        &self.position_environment.eval
+    }
+
+    // positions of the game history keep the weights they were evaluated with
+    pub fn set_eval_params_rust(&mut self, params: EvalParams) {
+        let mut environment = (*self.position_environment).clone();
+        environment.eval = params;
+        self.position_environment = Arc::new(environment);
+        self.current_position.environment = self.position_environment.clone();
+        self.current_position.eval = None;
+        self.tt.clear();
+        self.tree = None;
+    }
+
+    pub fn set_mcts_config_rust(&mut self, config: MctsConfig) {
+        if let Some(ref mut tree) = self.tree { tree.set_config(config.clone()); }
+        self.mcts_config = config;
+    }
+
+    // Runs the passes of the config on the tree, reused if it is rooted at the current
+    // position. The score is the value of the move for the side to move in thousandths,
+    // turned to white's.
+    pub fn best_move_mcts(&mut self) -> BestPos {
+        let key = self.current_position.zobrist();
+        if self.tree.as_ref().is_none_or(|x| x.position().zobrist() != key) {
+            self.tree = Some(McTree::with_config(self.current_position.clone(), self.position_history.clone(),
+                                                 self.mcts_config.clone()));
+        }
+        let mut on_info = self.on_info.take();
+        let config = self.mcts_config.clone();
+        let tree = self.tree.as_mut().unwrap();
+        let best = tree.search_with_info(config.passes, config.info_every, &mut |info| {
+            if let Some(ref mut on_info) = on_info { on_info(info); }
+        });
+        self.on_info = on_info;
+        match best {
+            Some(node) => {
+                let node = node.borrow();
+                let score = (node.value() * 1000.0) as i32 * self.sign();
+                self.best_pos(score, vec![node.get_move().unwrap()])
+            }
+            None => self.best_pos(0, vec![])
+        }
+    }
+
+    pub fn set_book(&mut self, book: Option<OpeningBook>) {
+        self.book = book;
+    }
+
+    pub fn book(&self) -> Option<&OpeningBook> {
+        self.book.as_ref()
+    }
+
+    // move of the opening book for the current position, scored by the static evaluation
+    pub fn book_move(&mut self) -> Option<BestPos> {
+        let move_item = self.book.as_ref()?.probe(&self.current_position, self.book_variety, &mut thread_rng())?;
+        let eval = self.current_position.evaluate();
+        Some(self.best_pos(eval, vec![move_item]))
+    }
+
+    // endgame tables probed by the search, their scores replace the evaluation
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+        self.tt.clear();
+    }
+
+    // Network evaluating the leaves of the alpha-beta search, None for the static evaluation.
+    // It has to be made for the board size of the game.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.network = network;
+        self.tt.clear();
+    }
+
+    pub fn network(&self) -> Option<&Arc<Network>> {
+        self.network.as_ref()
+    }
+
+    pub fn with_rules(rules: RuleSet) -> Game {
+        let environment = Arc::new(PositionEnvironment::with_rules(rules));
+        let position = Position::new(environment.clone());
+        let position_history = Rc::new(RefCell::new(PositionHistory::new()));
+        Game {
+            position_environment: environment.clone(),
+            current_position: position.clone(),
+            position_history,
+            max_depth: 3,
+            tree: None,
+            pdn_tags: vec![],
+            tt: TranspositionTable::new(TT_SIZE_MB),
+            search: SearchControl::default(),
+            search_limits: SearchLimits::default(),
+            on_info: None,
+            quiescence: QuiescenceConfig::default(),
+            ordering: MoveOrdering::new(position.cells.len()),
+            tablebase: None,
+            book: None,
+            book_variety: 0.5,
+            book_learned: false,
+            mcts_config: MctsConfig::default(),
+            network: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            pool: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            worker_tts: vec![],
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::notation::Notation;
     use crate::color::Color;
     use crate::game::Game;
     use crate::PositionHistory::FinishType::{BlackWin, Draw1, Draw2, Draw3, WhiteWin};
@@ -447,6 +797,58 @@ mod tests {
     }
 
 
+    #[test]
+    fn game_fen() {
+        let mut game = Game::new(8);
+        assert!(game.set_fen("W:W21,22,K5:B1,2,3").is_ok());
+        assert_eq!(game.get_fen(), "W:WK5,21,22:B1,2,3");
+        assert_eq!(game.current_position.next_move, Some(Color::White));
+        assert_eq!(game.get_move_list(false).list.len(), 9);
+    }
+
+    #[test]
+    fn transposition_table_search() {
+        let search = |tt_size: usize| {
+            let mut game = Game::new(8);
+            game.set_tt_size(tt_size);
+            game.set_fen("W:W17,21,22,23,24,25,27,28,29,30,31:B1,2,3,4,5,6,7,8,10,11,12").unwrap();
+            let first = game.best_move(5, i32::MIN, i32::MAX, 0);
+            // the second search starts with the table filled by the first one
+            let second = game.best_move(5, i32::MIN, i32::MAX, 0);
+            assert_eq!(first.deep_eval, second.deep_eval);
+            let mov = first.get_move_item();
+            (first.deep_eval, mov.from(), mov.to())
+        };
+        assert_eq!(search(0), search(8));
+    }
+
+    #[test]
+    fn principal_variation() {
+        let fen = "W:W17,21,22,23,24,25,27,28,29,30,31:B1,2,3,4,5,6,7,8,10,11,12";
+        let mut game = Game::new(8);
+        game.set_fen(fen).unwrap();
+        let best = game.best_move(4, i32::MIN, i32::MAX, 0);
+        assert!(best.pv.len() >= 5);
+        assert_eq!(best.pv[0].path(), best.get_move_item().path());
+        // the line is playable move by move
+        let env = game.position_environment.clone();
+        let mut pos = game.current_position.clone();
+        for move_item in &best.pv {
+            let text = move_item.text(&env, Notation::Algebraic).to_string();
+            let found = pos.get_move_list(true).find_move(&env, &text).unwrap().clone();
+            pos.make_move(&found);
+        }
+        assert_eq!(best.pv_text(&env, Notation::Numeric).split(' ').count(), best.pv.len());
+
+        let mut game = Game::new(8);
+        game.set_fen(fen).unwrap();
+        let best_moves = game.best_moves(4, 3);
+        assert_eq!(best_moves.len(), 3);
+        assert!(best_moves.windows(2).all(|x| x[0].deep_eval >= x[1].deep_eval));
+        assert_eq!(best_moves[0].deep_eval, best.deep_eval);
+        assert!(best_moves.iter().all(|x| x.pv.len() >= 5));
+    }
+
     #[test]
     fn finish_cmp() {
         assert_eq!(Draw2, Draw1);
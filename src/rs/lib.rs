@@ -11,6 +11,24 @@ pub mod color;
 pub mod game;
 pub mod mcts;
 pub mod PositionHistory;
+pub mod bitboard;
+mod perft;
+pub mod notation;
+mod fen;
+pub mod pdn;
+pub mod rules;
+pub mod zobrist;
+pub mod transposition;
+pub mod search;
+pub mod ordering;
+pub mod negamax;
+pub mod eval;
+pub mod tuning;
+pub mod tablebase;
+pub mod book;
+pub mod puct;
+pub mod features;
+pub mod nn;
 
 
 
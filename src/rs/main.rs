@@ -6,22 +6,12 @@ use crate::color::Color::Black;
 use crate::game::Game;
 use crate::mcts::McTree;
 use crate::piece::Piece;
+use crate::position::Position;
+use crate::notation::Notation;
+use std::time::Instant;
 
 include!("lib.rs");
 
-#[derive(Debug)]
-struct MoveAsStrike {
-    from: usize,
-    to: usize,
-    take: usize,
-}
-
-#[derive(Debug)]
-struct MoveAsQuite {
-    from: usize,
-    to: usize,
-}
-
 pub fn random_game_test() {
     let v_w: Vec<_> = vec![0; 12].iter().enumerate()
         .map(|(i, x)| if i / 4 % 2 == 0 { 2 * i } else { 2 * i + 1 }).collect();
@@ -72,18 +62,16 @@ pub fn best_move_triangle() {
     // game.position_history.borrow_mut().push(PositionAndMove::from_pos(game.current_position));
     game.tree = Some(McTree::new(game.current_position.clone(), game.position_history.clone()));
 
-    loop {
-        if let Some(mut tree) = game.tree {
-            // game.tree = Some(McTree::new(game.current_position.clone(), game.position_history.clone()));
-            let node = tree.search(100000);
-            if node.is_none() { break; }
-            game.tree = Option::from(McTree::new_from_node(node.clone().unwrap().clone(),
-                                                           game.position_history.clone()));
-            let mov = node.unwrap().borrow().get_move().unwrap().clone();
-            print!("{:?}\n", &mov);
-            game.make_move_by_move_item(&mov);
-            io::stdout().flush().unwrap();
-        }
+    game.tree.as_mut().unwrap().set_max_nodes(1_000_000);
+
+    while let Some(ref mut tree) = game.tree {
+        let node = tree.search(100000);
+        if node.is_none() { break; }
+        let mov = node.unwrap().borrow().get_move().unwrap();
+        // the game moves its tree along
+        game.make_move_by_move_item(&mov);
+        print!("{:?} {} nodes\n", &mov, game.tree.as_ref().map_or(0, |x| x.nodes()));
+        io::stdout().flush().unwrap();
     }
 
     let mut game = Game::new(8);
@@ -96,34 +84,174 @@ pub fn best_move_triangle() {
         .for_each(|pos|
             game.insert_piece(Piece::new(game.to_pack(*pos), Color::Black, true)));
 
-    use crate::moves::PieceMove;
     while game.position_history.borrow_mut().finish_check().is_none() {
         print!("state {}\n", game.state_());
         print!("history {:?}\n", game.position_history.borrow().len());
         let best = game.get_best_move_rust();
-        print!("{}", {
-            if best.get_move_item().strike.is_some() {
-                format!("move: {:?}\n", best.get_move_item().strike.unwrap().vec.iter().map(
-                    |x|
-                        MoveAsStrike {
-                            from: game.to_board(x.from()),
-                            to: game.to_board(x.to()),
-                            take: game.to_board(x.take().unwrap()),
-                        }).collect::<Vec<_>>())
-            } else {
-                format!("move: {:?}\n", [best.get_move_item().mov.unwrap()].iter().map(|x|
-                    MoveAsQuite {
-                        from: game.to_board(x.from()),
-                        to: game.to_board(x.to),
-                    }).collect::<Vec<_>>())
-            }
-        });
+        print!("move: {}\n", best.get_move_item().text(&game.current_position.environment, Notation::Algebraic));
         game.make_move_by_pos_item(&best);
     }
     print!("{:?}", game.position_history.borrow_mut().finish_check());
 }
 
+// main perft <depth> [fen] | main divide <depth> [fen]
+fn perft_command(args: &[String]) {
+    let depth: u32 = args.get(1).and_then(|x| x.parse().ok()).unwrap_or(6);
+    let game = Game::new(8);
+    let environment = game.current_position.environment.clone();
+    let mut pos = match args.get(2) {
+        Some(fen) => Position::from_fen(environment, fen).unwrap_or_else(|err| panic!("{}", err)),
+        None => Position::start_position(environment)
+    };
+    let now = Instant::now();
+    if args[0] == "divide" {
+        let mut total = 0;
+        for (move_item, nodes) in pos.divide(depth) {
+            print!("{}: {}\n", move_item.text(&pos.environment, Notation::Algebraic), nodes);
+            total += nodes;
+        }
+        print!("total: {}  {:.2?}\n", total, now.elapsed());
+    } else {
+        for d in 1..=depth {
+            print!("perft {}: {}  {:.2?}\n", d, pos.perft(d), now.elapsed());
+        }
+    }
+}
+
+const BENCH_FENS: [&str; 4] = [
+    "W:W21-32:B1-12",
+    "W:W17,21,22,23,24,25,27,28,29,30,31:B1,2,3,4,5,6,7,8,10,11,12",
+    "B:W18,21,22,24,25,26,27,29,30,31:B1,2,3,5,6,7,9,10,11,13",
+    "W:WK5,22,26,30:B9,13,15,16,K27",
+];
+
+// main bench [depth] [threads]: nodes searched with the evaluation ordering and the
+// killer/history one, and the time of the root split over the threads if more than one
+fn bench_command(args: &[String]) {
+    let depth: i16 = args.get(1).and_then(|x| x.parse().ok()).unwrap_or(6);
+    let threads: usize = args.get(2).and_then(|x| x.parse().ok()).unwrap_or(1);
+    let mut totals = [0, 0];
+    for fen in BENCH_FENS {
+        let mut line = format!("{:64}", fen);
+        for (i, heuristic) in [false, true].into_iter().enumerate() {
+            let mut game = Game::new(8);
+            game.set_fen(fen).unwrap();
+            game.set_heuristic_ordering(heuristic);
+            let now = Instant::now();
+            let best = game.best_move(depth, i32::MIN, i32::MAX, 0);
+            let nodes = game.search_stats().nodes;
+            totals[i] += nodes;
+            line += &format!("  {:>10} nodes {:>6} {:.2?}", nodes,
+                             best.get_move_item().text(&game.current_position.environment, Notation::Algebraic),
+                             now.elapsed());
+        }
+        if threads > 1 {
+            let mut game = Game::new(8);
+            game.set_fen(fen).unwrap();
+            let now = Instant::now();
+            let best = game.best_move_parallel(depth, threads);
+            line += &format!("  {:>10} nodes {:>6} {:.2?} on {} threads", game.search_stats().nodes,
+                             best.get_move_item().text(&game.current_position.environment, Notation::Algebraic),
+                             now.elapsed(), threads);
+        }
+        print!("{}\n", line);
+    }
+    print!("total: evaluation ordering {} nodes, killer/history ordering {} nodes\n", totals[0], totals[1]);
+}
+
+// main tune [games] [depth] [output] [params]: Texel tuning of the evaluation weights (those of
+// the `params` JSON file or the defaults) on positions of self-play games, written to `output`
+fn tune_command(args: &[String]) {
+    use crate::eval::EvalParams;
+    use crate::tuning::{error, fit_k, self_play, tune};
+    let games: usize = args.get(1).and_then(|x| x.parse().ok()).unwrap_or(100);
+    let depth: i16 = args.get(2).and_then(|x| x.parse().ok()).unwrap_or(3);
+    let output = args.get(3).map_or("eval_params.json", |x| x.as_str());
+    let params = match args.get(4) {
+        Some(path) => {
+            let json = std::fs::read_to_string(path).unwrap_or_else(|err| panic!("{}: {}", path, err));
+            EvalParams::from_json(&json).unwrap_or_else(|err| panic!("{}: {}", path, err))
+        }
+        None => EvalParams::default()
+    };
+    let mut game = Game::new(8);
+    game.set_eval_params_rust(params.clone());
+    game.set_start_position();
+    let now = Instant::now();
+    let samples = self_play(&game, games, depth, 6, &mut thread_rng());
+    print!("{} positions of {} games  {:.2?}\n", samples.len(), games, now.elapsed());
+    let k = fit_k(&samples, &params.weights());
+    print!("k {:e}, error {:.6}\n", k, error(&samples, &params.weights(), k));
+    let (tuned, tuned_error) = tune(&samples, &params, k, 64, |pass, error| {
+        print!("pass {}: error {:.6}\n", pass, error);
+    });
+    print!("{}\nerror {:.6}  {:.2?}\n", tuned.to_json(), tuned_error, now.elapsed());
+    std::fs::write(output, tuned.to_json()).unwrap_or_else(|err| panic!("{}: {}", output, err));
+}
+
+// tablebase [pieces] [output]: tables of up to `pieces` pieces written to `output`
+fn tablebase_command(args: &[String]) {
+    use crate::tablebase::Tablebase;
+    let pieces: usize = args.get(1).and_then(|x| x.parse().ok()).unwrap_or(4);
+    let output = args.get(2).map_or("tablebase.bin", |x| x.as_str());
+    let now = Instant::now();
+    let tablebase = Tablebase::generate_with(pieces, |material, positions| {
+        print!("{:?}: {} positions  {:.2?}\n", material, positions, now.elapsed());
+    });
+    let mut file = io::BufWriter::new(std::fs::File::create(output).unwrap_or_else(|err| panic!("{}: {}", output, err)));
+    tablebase.write(&mut file).and_then(|_| file.flush()).unwrap_or_else(|err| panic!("{}: {}", output, err));
+    print!("{} tables  {:.2?}\n", tablebase.len(), now.elapsed());
+}
+
+// book <games | file.pdn> [output] [plies] [depth]: opening book of a PDN file or of
+// self-play games, added to `output` if it exists
+fn book_command(args: &[String]) {
+    use crate::book::{OpeningBook, BOOK_PLIES};
+    let source = args.get(1).map_or("100", |x| x.as_str());
+    let output = args.get(2).map_or("book.json", |x| x.as_str());
+    let plies: usize = args.get(3).and_then(|x| x.parse().ok()).unwrap_or(BOOK_PLIES);
+    let depth: i16 = args.get(4).and_then(|x| x.parse().ok()).unwrap_or(4);
+    let mut book = match std::fs::read_to_string(output) {
+        Ok(json) => OpeningBook::from_json(&json).unwrap_or_else(|err| panic!("{}: {}", output, err)),
+        Err(_) => OpeningBook::new(plies)
+    };
+    let mut game = Game::new(8);
+    game.set_start_position();
+    let now = Instant::now();
+    match source.parse::<usize>() {
+        Ok(games) => book.self_play(&game, games, depth, 2, &mut thread_rng()),
+        Err(_) => {
+            let text = std::fs::read_to_string(source).unwrap_or_else(|err| panic!("{}: {}", source, err));
+            let games = book.add_pdn(&mut game, &text).unwrap_or_else(|err| panic!("{}: {}", source, err));
+            print!("{} games\n", games);
+        }
+    }
+    print!("{} positions  {:.2?}\n", book.len(), now.elapsed());
+    std::fs::write(output, book.to_json()).unwrap_or_else(|err| panic!("{}: {}", output, err));
+}
+
 pub fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some("perft" | "divide") = args.first().map(|x| x.as_str()) {
+        perft_command(&args);
+        return;
+    }
+    if let Some("bench") = args.first().map(|x| x.as_str()) {
+        bench_command(&args);
+        return;
+    }
+    if let Some("tune") = args.first().map(|x| x.as_str()) {
+        tune_command(&args);
+        return;
+    }
+    if let Some("tablebase") = args.first().map(|x| x.as_str()) {
+        tablebase_command(&args);
+        return;
+    }
+    if let Some("book") = args.first().map(|x| x.as_str()) {
+        book_command(&args);
+        return;
+    }
     best_move_triangle();
     // random_game_test();
     let mut game = Game::new(8);
@@ -163,7 +291,6 @@ pub fn main() {
         .for_each(|pos|
             game.insert_piece(Piece::new(game.to_pack(*pos), Color::Black, false)));
     game.current_position.next_move = Some(Color::White);
-    use std::time::Instant;
     let now = Instant::now();
     for _i in 0..1000000 {
         let mut list = game.current_position.get_move_list(false);
@@ -10,74 +10,393 @@ use rand::{Rng};
 use schemars::_private::NoSerialize;
 use crate::color::Color;
 use crate::moves_list::MoveItem;
+use crate::notation::Notation;
+use crate::search::{now_ms, QuiescenceConfig, SearchControl, SearchInfo, SearchStats};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use crate::negamax::{Searcher, INFINITY};
+use crate::ordering::MoveOrdering;
+use crate::transposition::TranspositionTable;
 
+// evaluation worth a value of tanh(1) for a rollout cut at its depth cap
+pub(crate) const EVAL_SCALE: f64 = 1000.0;
+// table of the alpha-beta rollouts
+const ROLLOUT_TT_MB: usize = 1;
+
+// how a newly reached leaf is valued
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum Rollout {
+    // no rollout: the tree itself grows down to finished games
+    None,
+    // random moves
+    Random,
+    // the move with the best static evaluation for the side to move
+    Eval,
+    // an alpha-beta search of alpha_beta_depth plies, no moves played
+    AlphaBeta,
+}
+
+// root child played after the search
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum FinalSelection {
+    MaxVisits,
+    // the highest mean value
+    MaxValue,
+    // the most visited child if it also has the highest value, otherwise the one with the
+    // highest lower confidence bound
+    Robust,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(default)]
+pub struct MctsConfig {
+    // weight of the UCT exploration term
+    pub exploration: f64,
+    pub rollout: Rollout,
+    // plies of a rollout, the evaluation decides at the cap
+    pub rollout_depth: usize,
+    pub alpha_beta_depth: i16,
+    // value of a child not visited yet, from -1 to 1; none picks one at random while all the
+    // childs are unvisited and scores the others by exploration only
+    pub first_play_urgency: Option<f64>,
+    pub selection: FinalSelection,
+    pub passes: i32,
+    // tree budget, 0 for none
+    pub max_nodes: usize,
+    // passes between infos of Game::get_best_move_mcts, none if 0
+    pub info_every: i32,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        MctsConfig {
+            exploration: 5.1,
+            rollout: Rollout::None,
+            rollout_depth: 40,
+            alpha_beta_depth: 2,
+            first_play_urgency: None,
+            selection: FinalSelection::MaxVisits,
+            passes: 10000,
+            max_nodes: 0,
+            info_every: 1000,
+        }
+    }
+}
+
+impl MctsConfig {
+    pub fn from_json(json: &str) -> Result<MctsConfig, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+// 1 for a win of `color`, -1 for a loss, 0 for a draw
+pub(crate) fn finish_value(finish: &FinishType, color: Color) -> f64 {
+    match finish {
+        FinishType::WhiteWin => if color == Color::White { 1.0 } else { -1.0 },
+        FinishType::BlackWin => if color == Color::Black { 1.0 } else { -1.0 },
+        _ => 0.0
+    }
+}
+
+// value of an evaluation of the side to move
+pub(crate) fn eval_value(eval: i32) -> f64 {
+    (eval as f64 / EVAL_SCALE).tanh()
+}
+
+// negamax state of the alpha-beta rollouts, kept between them
+#[derive(Debug)]
+struct RolloutSearch {
+    tt: TranspositionTable,
+    ordering: MoveOrdering,
+    control: SearchControl,
+    quiescence: QuiescenceConfig,
+}
+
+// bytes taken by a node: the node, its Rc counts and its place among the childs of its parent
+pub const NODE_BYTES: usize = std::mem::size_of::<RefCell<Node>>() + 3 * std::mem::size_of::<usize>();
+
+// Node of the tree: only the move leading to it is kept, positions are replayed from the root
 #[derive(Debug)]
 pub struct Node {
-    W: i64,
-    N: i64,
+    pub(crate) W: f64,
+    pub(crate) N: i64,
+    // prior of the move for PUCT
+    pub(crate) P: f32,
     passed_completely: bool,
-    pos_mov: Rc<RefCell<PositionAndMove>>,
-    childs: Vec<Rc<RefCell<Node>>>,
+    pub(crate) mov: Option<MoveItem>,
+    pub(crate) childs: Vec<Rc<RefCell<Node>>>,
 }
 
 impl Node {
-    pub fn new(pos_mov: PositionAndMove) -> Node {
+    pub fn new(mov: Option<MoveItem>) -> Node {
         Node {
-            W: 0,
+            W: 0.0,
             N: 0,
+            P: 0.0,
             passed_completely: false,
-            pos_mov: Rc::new(RefCell::new(pos_mov)),
+            mov,
             childs: vec![],
         }
     }
-    pub fn expand(&mut self) {
-        if self.childs.len() > 0 { return; }
-        let mut base_p = self.pos_mov.borrow().pos.clone();
-        let move_list = base_p.get_move_list_cached();
+
+    // a child per move of `pos`, the position of the node; returns the number added
+    pub fn expand(&mut self, pos: &mut Position) -> usize {
+        if self.childs.len() > 0 { return 0; }
+        let move_list = pos.get_move_list_cached();
         for mov in &move_list.as_ref().as_ref().unwrap().list {
-            self.childs.push(Rc::new(
-                RefCell::new(Node::new(base_p.make_move_and_get_position(mov)))));
-            base_p.unmake_move(mov);
+            self.childs.push(Rc::new(RefCell::new(Node::new(Some(mov.clone())))));
         }
+        self.childs.len()
     }
+
     pub fn get_move(&self) -> Option<MoveItem> {
-        self.pos_mov.borrow().mov.clone()
+        self.mov.clone()
+    }
+
+    pub fn visits(&self) -> i64 {
+        self.N
+    }
+
+    pub fn prior(&self) -> f32 {
+        self.P
+    }
+
+    // mean result for the side that made the move, from -1 to 1
+    pub fn value(&self) -> f64 {
+        if self.N == 0 { 0.0 } else { self.W / self.N as f64 }
+    }
+
+    // nodes of the subtree, the node included
+    pub fn size(&self) -> usize {
+        1 + self.childs.iter().map(|x| x.borrow().size()).sum::<usize>()
     }
 }
 
 #[derive(Debug)]
 pub struct McTree {
-    root: Rc<RefCell<Node>>,
-    history: Rc<RefCell<PositionHistory>>,
+    pub(crate) root: Rc<RefCell<Node>>,
+    // position of the root
+    pub(crate) pos: Position,
+    pub(crate) history: Rc<RefCell<PositionHistory>>,
+    pub(crate) nodes: usize,
+    pub(crate) max_nodes: usize,
+    config: MctsConfig,
+    rollout_search: Option<Box<RolloutSearch>>,
+    // the priors of the root childs have their noise, mixed in once per root
+    pub(crate) root_noised: bool,
 }
 
 impl McTree {
     pub fn new(pos: Position, history: Rc<RefCell<PositionHistory>>) -> McTree {
-        McTree {
-            root: Rc::new(RefCell::new(Node {
-                W: 0,
-                N: 0,
-                passed_completely: false,
-                pos_mov: Rc::new(RefCell::new(PositionAndMove::from_pos(pos))),
-                childs: vec![],
-            })),
-            history,
-        }
+        McTree::with_config(pos, history, MctsConfig::default())
     }
 
-    pub fn new_from_node(root: Rc<RefCell<Node>>, history: Rc<RefCell<PositionHistory>>) -> McTree {
-        McTree {
-            root,
+    pub fn with_config(pos: Position, history: Rc<RefCell<PositionHistory>>, config: MctsConfig) -> McTree {
+        let mut tree = McTree {
+            root: Rc::new(RefCell::new(Node::new(None))),
+            pos,
             history,
+            nodes: 1,
+            max_nodes: usize::MAX,
+            config: MctsConfig::default(),
+            rollout_search: None,
+            root_noised: false,
+        };
+        tree.set_config(config);
+        tree
+    }
+
+    pub fn config(&self) -> &MctsConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: MctsConfig) {
+        self.max_nodes = if config.max_nodes > 0 { config.max_nodes.max(2) } else { usize::MAX };
+        self.config = config;
+    }
+
+    // the least visited subtrees are dropped when the tree grows past `max_nodes`
+    pub fn set_max_nodes(&mut self, max_nodes: usize) {
+        self.max_nodes = max_nodes.max(2);
+    }
+
+    pub fn set_memory_limit(&mut self, bytes: usize) {
+        self.set_max_nodes(bytes / NODE_BYTES);
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+
+    pub fn root(&self) -> Rc<RefCell<Node>> {
+        self.root.clone()
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.pos
+    }
+
+    // Re-roots the tree on the child of `mov`, played by either side, and drops the other
+    // childs. A move of no child starts a new tree. False, and the tree is left as it is,
+    // if the move is not legal in the position of the root.
+    pub fn advance(&mut self, mov: &MoveItem) -> bool {
+        let move_list = self.pos.get_move_list_cached();
+        let legal = move_list.as_ref().as_ref().unwrap().list.iter()
+            .any(|x| x.strike.is_some() == mov.strike.is_some() && x.path() == mov.path());
+        if !legal { return false; }
+        let mut pos = self.pos.clone();
+        pos.make_move(mov);
+        let key = pos.zobrist();
+        let child = self.root.borrow().childs.iter().find(|child| {
+            let child_mov = child.borrow().mov.clone().unwrap();
+            self.pos.make_move(&child_mov);
+            let found = self.pos.zobrist() == key;
+            self.pos.unmake_move(&child_mov);
+            found
+        }).cloned();
+        self.root = child.unwrap_or_else(|| Rc::new(RefCell::new(Node::new(None))));
+        self.root.borrow_mut().mov = None;
+        self.root_noised = false;
+        self.nodes = self.root.borrow().size();
+        self.pos = pos;
+        true
+    }
+
+    // Drops the childs of the least visited nodes whose childs are all leaves, round by round,
+    // until the tree takes three quarters of its budget. The nodes keep their counts and
+    // expand again when visited.
+    pub fn prune(&mut self) {
+        let target = self.max_nodes / 4 * 3;
+        while self.nodes > target {
+            let mut frontier = vec![];
+            let mut stack: Vec<Rc<RefCell<Node>>> = self.root.borrow().childs.clone();
+            while let Some(node) = stack.pop() {
+                let node_ref = node.borrow();
+                if node_ref.childs.is_empty() { continue; }
+                if node_ref.childs.iter().all(|x| x.borrow().childs.is_empty()) {
+                    frontier.push(node.clone());
+                } else {
+                    stack.extend(node_ref.childs.iter().cloned());
+                }
+            }
+            if frontier.is_empty() { break; }
+            frontier.sort_by_key(|x| x.borrow().N);
+            for node in frontier {
+                if self.nodes <= target { break; }
+                let mut node = node.borrow_mut();
+                self.nodes -= node.childs.len();
+                node.childs = vec![];
+            }
         }
     }
 
     pub fn search(&mut self, max_passes: i32) -> Option<Rc<RefCell<Node>>> {
+        self.search_with_info(max_passes, 0, &mut |_| {})
+    }
+
+    // most visited line from the root and the win rate of its first move
+    fn info(&self, stats: &SearchStats) -> SearchInfo {
+        let mut pv = vec![];
+        let mut score = 0;
+        let mut node = self.root.clone();
+        let environment = self.pos.environment.clone();
+        loop {
+            let best = node.borrow().childs.iter().max_by_key(|x| x.borrow().N).cloned();
+            match best {
+                Some(best) if best.borrow().N > 0 => {
+                    if pv.is_empty() { score = (best.borrow().value() * 1000.0) as i32; }
+                    pv.push(best.borrow().mov.as_ref().unwrap().text(&environment, Notation::Algebraic).to_string());
+                    node = best;
+                }
+                _ => break
+            }
+        }
+        SearchInfo { stats: stats.clone(), score, pv }
+    }
+
+    // Value of a leaf first reached by a move of `mover`, for `mover`, by the rollout of
+    // the config. The moves played go on the history, cut back by the caller.
+    fn rollout(&mut self, mut pos: Position, mover: Color) -> f64 {
+        let config = self.config.clone();
+        if config.rollout == Rollout::AlphaBeta {
+            let path: Vec<u64> = self.history.borrow().iter().map(|x| x.borrow().pos.zobrist()).collect();
+            let quiet_moves = self.history.borrow().quiet_moves();
+            let cells = pos.cells.len();
+            let search = self.rollout_search.get_or_insert_with(|| Box::new(RolloutSearch {
+                tt: TranspositionTable::new(ROLLOUT_TT_MB),
+                ordering: MoveOrdering::new(cells),
+                control: SearchControl::default(),
+                quiescence: QuiescenceConfig::default(),
+            }));
+            search.ordering.new_search();
+            let mut searcher = Searcher::new(&mut pos, &mut search.tt, &mut search.ordering, &mut search.control,
+                                             &search.quiescence, path).with_quiet_moves(quiet_moves);
+            // the score is the one of the side to move
+            return eval_value(-searcher.root(config.alpha_beta_depth.max(1), -INFINITY, INFINITY));
+        }
+        let mut rng = rand::thread_rng();
+        for _ in 0..config.rollout_depth {
+            let move_list = pos.get_move_list_cached();
+            let list = &move_list.as_ref().as_ref().unwrap().list;
+            if list.is_empty() { break; }
+            let mov = if config.rollout == Rollout::Eval {
+                let sign = if pos.next_move == Some(Color::White) { 1 } else { -1 };
+                list.iter().max_by_key(|mov| {
+                    pos.make_move(mov);
+                    let eval = pos.evaluate() * sign;
+                    pos.unmake_move(mov);
+                    eval
+                }).unwrap().clone()
+            } else {
+                list[rng.gen_range(0..list.len())].clone()
+            };
+            pos.make_move(&mov);
+            let finish = self.history.borrow_mut().push(PositionAndMove::from(pos.clone(), mov));
+            if let Some(finish) = finish { return finish_value(&finish, mover); }
+            pos.state = self.history.borrow_mut().last().borrow().pos.state.clone();
+        }
+        let eval = pos.evaluate();
+        eval_value(if mover == Color::White { eval } else { -eval })
+    }
+
+    // root child to play by the selection of the config
+    pub fn best_child(&self) -> Option<Rc<RefCell<Node>>> {
+        let root = self.root.borrow();
+        let most_visited = root.childs.iter().max_by_key(|x| x.borrow().N).cloned();
+        let best_value = root.childs.iter().filter(|x| x.borrow().N > 0)
+            .max_by(|a, b| a.borrow().value().total_cmp(&b.borrow().value())).cloned();
+        match self.config.selection {
+            FinalSelection::MaxVisits => most_visited,
+            FinalSelection::MaxValue => best_value.or(most_visited),
+            FinalSelection::Robust => {
+                if most_visited.as_ref().zip(best_value.as_ref()).is_some_and(|(a, b)| Rc::ptr_eq(a, b)) {
+                    return most_visited;
+                }
+                let ln = f64::ln(root.N.max(1) as f64);
+                let bound = |x: &Node| x.value() - f64::sqrt(2.0 * ln / x.N as f64);
+                root.childs.iter().filter(|x| x.borrow().N > 0)
+                    .max_by(|a, b| bound(&a.borrow()).total_cmp(&bound(&b.borrow()))).cloned().or(most_visited)
+            }
+        }
+    }
+
+    // `on_info` is called every `info_every` passes, never if it is 0
+    pub fn search_with_info(&mut self, max_passes: i32, info_every: i32,
+                            on_info: &mut dyn FnMut(&SearchInfo)) -> Option<Rc<RefCell<Node>>> {
+        let start = now_ms();
+        let mut stats = SearchStats::default();
         let mut track: Vec<Rc<RefCell<Node>>> = vec![];
         let hist_len = self.history.borrow().len();
-        fn back_propagation(mut res: i64, track: &mut Vec<Rc<RefCell<Node>>>,
+        fn back_propagation(mut res: f64, track: &mut Vec<Rc<RefCell<Node>>>,
                                 history: &Rc<RefCell<PositionHistory>>, hist_len: usize) {
-            if res != 0 {
+            if res != 0.0 {
                 for node in track.iter().rev() {
                     node.borrow_mut().W += res;
                     res = -res;
@@ -86,22 +405,25 @@ impl McTree {
             history.borrow_mut().cut_to(hist_len);
             *track = vec![];
         }
+        let exploration = self.config.exploration;
+        let first_play_urgency = self.config.first_play_urgency;
         let mut pass = 0;
         while pass < max_passes && !self.root.borrow().passed_completely {
             let mut node = self.root.clone();
+            let mut pos = self.pos.clone();
             loop {
                 node.borrow_mut().N += 1;
-                node.borrow_mut().expand();
+                self.nodes += node.borrow_mut().expand(&mut pos);
                 let u = |child: &Node|
-                    5.1 * f64::sqrt(f64::ln(node.borrow().N as f64) / (child.N as f64 + 1.0));
-                let u_max = |node: &Node| node.W as f64 / (node.N as f64 + 1.0) + u(node);
+                    exploration * f64::sqrt(f64::ln(node.borrow().N as f64) / (child.N as f64 + 1.0));
+                let u_max = |node: &Node| match first_play_urgency {
+                    Some(value) if node.N == 0 => value + u(node),
+                    _ => node.W / (node.N as f64 + 1.0) + u(node)
+                };
                 let childs = node.borrow().childs.clone();
-                // for child in &node.borrow().childs {
-                //     if !child.borrow().passed_completely { childs.push(child.clone()); }
-                // }
                 if childs.len() > 0 {
                     node = {
-                        if childs.iter().all(|x| x.borrow().N == 0) {
+                        if first_play_urgency.is_none() && childs.iter().all(|x| x.borrow().N == 0) {
                             childs[rand::thread_rng().gen_range(0..childs.len())].clone()
                         } else {
                             childs.iter().max_by(|a, b|
@@ -117,27 +439,194 @@ impl McTree {
                 }
 
                 track.push(node.clone());
-                let finish = self.history.borrow_mut().push_rc(node.borrow().pos_mov.clone());
+                stats.depth = stats.depth.max(track.len() as i16);
+                let mov = node.borrow().mov.clone().unwrap();
+                let mover = pos.next_move.unwrap();
+                pos.make_move(&mov);
+                let finish = self.history.borrow_mut().push(PositionAndMove::from(pos.clone(), mov));
+                // the draw counters set by finish_check go on down the line
+                pos.state = self.history.borrow_mut().last().borrow().pos.state.clone();
                 // if finish achieved
                 if let Some(finish) = finish {
+                    node.borrow_mut().N += 1;
                     node.borrow_mut().passed_completely = true;
-                    back_propagation({
-                                         let fr = if finish == FinishType::WhiteWin { 1 } else if
-                                         finish == FinishType::BlackWin { -1 } else { 0 };
-                                         let sing =
-                                             if node.borrow().pos_mov.borrow().pos.next_move.unwrap() == Color::White { -1 } else { 1 };
-                                         fr * sing
-                                     }, &mut track, &self.history, hist_len);
+                    back_propagation(finish_value(&finish, mover), &mut track, &self.history, hist_len);
+                    break;
+                }
+                // a leaf reached for the first time is valued by its rollout
+                if self.config.rollout != Rollout::None && node.borrow().N == 0 {
+                    node.borrow_mut().N += 1;
+                    let value = self.rollout(pos, mover);
+                    back_propagation(value, &mut track, &self.history, hist_len);
                     break;
                 }
             }
+            if self.nodes > self.max_nodes { self.prune(); }
             pass += 1;
+            if info_every > 0 && pass % info_every == 0 {
+                stats.nodes = pass as u64;
+                stats.set_time(start);
+                on_info(&self.info(&stats));
+            }
         }
-        if self.root.borrow().childs.len() > 0 {
-            Some(self.root.borrow().childs
-                .iter().max_by(|x, y| x.borrow().N.cmp(&y.borrow().N)).unwrap().clone())
-        } else {
-            None
+        self.best_child()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::mcts::{FinalSelection, McTree, MctsConfig, Rollout, NODE_BYTES};
+    use crate::notation::Notation;
+    use crate::position::Position;
+
+    #[test]
+    fn search_info() {
+        let game = Game::new(8);
+        let pos = Position::start_position(game.current_position.environment.clone());
+        let mut tree = McTree::new(pos, game.position_history.clone());
+        let mut infos = vec![];
+        let best = tree.search_with_info(300, 100, &mut |info| infos.push(info.clone()));
+        assert_eq!(infos.iter().map(|x| x.stats.nodes).collect::<Vec<_>>(), vec![100, 200, 300]);
+        let last = infos.last().unwrap();
+        assert!(last.stats.depth > 1);
+        assert!(!last.pv.is_empty());
+        let best = best.unwrap().borrow().get_move().unwrap();
+        let env = game.current_position.environment.clone();
+        assert_eq!(last.pv[0], best.text(&env, Notation::Algebraic).to_string());
+    }
+
+    #[test]
+    fn advance() {
+        let mut game = Game::new(8);
+        game.set_start_position();
+        let mut tree = McTree::new(game.current_position.clone(), game.position_history.clone());
+        let best = tree.search(500).unwrap();
+        let visits = best.borrow().visits();
+        let size = best.borrow().size();
+        assert_eq!(tree.nodes(), tree.root().borrow().size());
+        // our move keeps its subtree and drops the others
+        let mov = best.borrow().get_move().unwrap();
+        assert!(tree.advance(&mov));
+        game.make_move_by_move_item(&mov);
+        assert_eq!(tree.root().borrow().visits(), visits);
+        assert_eq!(tree.nodes(), size);
+        assert!(tree.root().borrow().get_move().is_none());
+        assert_eq!(tree.position().to_fen(), game.get_fen());
+        // the reply of the opponent, searched or not
+        let reply = tree.root().borrow().childs.iter().min_by_key(|x| x.borrow().visits()).unwrap()
+            .borrow().get_move().unwrap();
+        assert!(tree.advance(&reply));
+        game.make_move_by_move_item(&reply);
+        assert_eq!(tree.position().to_fen(), game.get_fen());
+        // the first move again, its square is empty now
+        let nodes = tree.nodes();
+        assert!(!tree.advance(&mov));
+        assert_eq!(tree.position().to_fen(), game.get_fen());
+        assert_eq!(tree.nodes(), nodes);
+        assert!(tree.search(200).is_some());
+        assert_eq!(game.position_history.borrow().len(), 2);
+        // a move out of the tree starts a new one
+        let mov = game.current_position.get_move_list(false).list[0].clone();
+        let mut other = McTree::new(game.current_position.clone(), game.position_history.clone());
+        assert!(other.advance(&mov));
+        assert_eq!(other.nodes(), 1);
+        // the game moves its own tree once per move
+        game.tree = Some(McTree::new(game.current_position.clone(), game.position_history.clone()));
+        game.make_move_by_move_item(&mov);
+        assert_eq!(game.tree.as_ref().unwrap().position().to_fen(), game.get_fen());
+    }
+
+    #[test]
+    fn node_budget() {
+        let game = Game::new(8);
+        let pos = Position::start_position(game.current_position.environment.clone());
+        let mut tree = McTree::new(pos, game.position_history.clone());
+        tree.set_max_nodes(300);
+        let best = tree.search(2000);
+        assert!(best.is_some());
+        assert!(tree.nodes() <= 300 + 20, "{}", tree.nodes());
+        assert_eq!(tree.nodes(), tree.root().borrow().size());
+        // the root keeps every visit
+        assert_eq!(tree.root().borrow().visits(), 2000);
+        // a node is a move and the counts, no position
+        assert!(NODE_BYTES < std::mem::size_of::<Position>());
+        tree.set_memory_limit(100 * NODE_BYTES);
+        tree.search(100);
+        assert!(tree.nodes() <= 100 + 20);
+    }
+
+    fn tree(fen: &str, config: MctsConfig) -> (Game, McTree) {
+        let mut game = Game::new(8);
+        game.set_fen(fen).unwrap();
+        let tree = McTree::with_config(game.current_position.clone(), game.position_history.clone(), config);
+        (game, tree)
+    }
+
+    #[test]
+    fn config() {
+        let config = MctsConfig::from_json(r#"{"rollout": "AlphaBeta", "selection": "Robust", "first_play_urgency": 0.5}"#).unwrap();
+        assert_eq!(config, MctsConfig {
+            rollout: Rollout::AlphaBeta,
+            selection: FinalSelection::Robust,
+            first_play_urgency: Some(0.5),
+            ..MctsConfig::default()
+        });
+        assert_eq!(MctsConfig::from_json(&config.to_json()).unwrap(), config);
+        assert!(MctsConfig::from_json(r#"{"rollout": "Deep"}"#).is_err());
+    }
+
+    #[test]
+    fn rollouts() {
+        for rollout in [Rollout::None, Rollout::Random, Rollout::Eval, Rollout::AlphaBeta] {
+            for selection in [FinalSelection::MaxVisits, FinalSelection::MaxValue, FinalSelection::Robust] {
+                let config = MctsConfig { rollout, selection, rollout_depth: 10, ..MctsConfig::default() };
+                // the king is lost at once: 22-26 hangs it, the others don't
+                let (game, mut tree) = tree("W:WK22,K29:B17,8", config);
+                let history = game.position_history.borrow().len();
+                let best = tree.search(300).unwrap();
+                assert_eq!(game.position_history.borrow().len(), history);
+                let value = best.borrow().value();
+                assert!((-1.0..=1.0).contains(&value), "{:?} {:?} {}", rollout, selection, value);
+                assert_eq!(tree.root().borrow().visits(), 300);
+                assert_eq!(tree.nodes(), tree.root().borrow().size());
+            }
         }
+        // a strike taking the last piece ends the game: every pass is a win
+        let (_, mut tree) = tree("W:WK22:B18", MctsConfig { rollout: Rollout::Random, ..MctsConfig::default() });
+        let best = tree.search(50).unwrap();
+        assert_eq!(best.borrow().value(), 1.0);
+    }
+
+    #[test]
+    fn first_play_urgency() {
+        // with a high urgency every root child is tried before any is tried twice
+        let config = MctsConfig { rollout: Rollout::Eval, first_play_urgency: Some(10.0), ..MctsConfig::default() };
+        let (_, mut tree) = tree("W:W21-32:B1-12", config);
+        tree.search(7);
+        let root = tree.root();
+        let visits: Vec<_> = root.borrow().childs.iter().map(|x| x.borrow().visits()).collect();
+        assert_eq!(visits, vec![1; 7]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn game_mcts() {
+        let mut game = Game::new(8);
+        game.set_start_position();
+        game.set_mcts_config_rust(MctsConfig { passes: 200, info_every: 100, rollout: Rollout::Eval, ..MctsConfig::default() });
+        let infos = std::rc::Rc::new(std::cell::RefCell::new(0));
+        let counter = infos.clone();
+        game.set_info_callback(move |_| *counter.borrow_mut() += 1);
+        let best = game.best_move_mcts();
+        assert_eq!(*infos.borrow(), 2);
+        assert_eq!(best.pv.len(), 1);
+        game.make_move_by_pos_item(&best);
+        // the tree follows the game and is searched on
+        let visits = game.tree.as_ref().unwrap().root().borrow().visits();
+        assert!(visits > 0);
+        assert_eq!(game.tree.as_ref().unwrap().position().to_fen(), game.get_fen());
+        game.best_move_mcts();
+        assert_eq!(game.tree.as_ref().unwrap().root().borrow().visits(), visits + 200);
+        assert!(game.get_mcts_config().contains("\"passes\":200"));
+    }
+}
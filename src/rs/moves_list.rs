@@ -109,6 +109,13 @@ impl MoveList {
             current_chain: Strike { vec: Vec::new(), king_move: false },
         }
     }
+
+    // majority rule: drops strikes taking fewer pieces than the longest one
+    pub fn retain_longest_strikes(&mut self) {
+        let len = |x: &MoveItem| x.strike.as_ref().map_or(0, |x| x.vec.len());
+        let max = self.list.iter().map(len).max().unwrap_or(0);
+        self.list.retain(|x| len(x) == max);
+    }
 }
 
 
@@ -0,0 +1,408 @@
+use std::cmp::Reverse;
+use crate::color::Color;
+use crate::moves_list::MoveItem;
+use crate::nn::Network;
+use crate::ordering::MoveOrdering;
+use crate::piece::Piece;
+use crate::position::Position;
+use crate::search::{QuiescenceConfig, SearchControl};
+use crate::tablebase::{Tablebase, TB_WIN};
+use crate::transposition::TranspositionTable;
+
+// bounds of the search window, negatable unlike i32::MIN
+pub const INFINITY: i32 = i32::MAX - 1;
+pub const MAX_PLY: usize = 128;
+// half width of the first aspiration window, a fifth of a man
+const ASPIRATION_WINDOW: i32 = 200;
+// score of a won position less the plies to the win, on the scale of the tablebase values
+pub const WIN: i32 = TB_WIN;
+// scores beyond it are wins or losses in a number of plies
+const WIN_BOUND: i32 = WIN / 2;
+
+// Negamax principal variation search on a Position: scores are for the side to move,
+// positive when it is ahead. Repetitions and the kings-only and quiet move limits of the
+// rules are draws in the search; the Russian endings rules are left to finish_check.
+pub struct Searcher<'a> {
+    pos: &'a mut Position,
+    tt: &'a mut TranspositionTable,
+    ordering: &'a mut MoveOrdering,
+    control: &'a mut SearchControl,
+    quiescence: &'a QuiescenceConfig,
+    // keys of the game and the search line before the current position, for repetitions
+    path: Vec<u64>,
+    // triangular table, pv[ply] is the best line found from ply
+    pv: Vec<Vec<MoveItem>>,
+    // best root move so far, kept also when the root fails low and has no line
+    root_best: Option<MoveItem>,
+    // exact values of the positions with few pieces
+    tablebase: Option<&'a Tablebase>,
+    // evaluation of the leaves instead of the static one
+    network: Option<&'a Network>,
+    // kings' moves without strikes in a row before the current position, by ply of the line
+    quiet_moves: Vec<usize>,
+}
+
+impl<'a> Searcher<'a> {
+    pub fn new(pos: &'a mut Position, tt: &'a mut TranspositionTable, ordering: &'a mut MoveOrdering,
+               control: &'a mut SearchControl, quiescence: &'a QuiescenceConfig, path: Vec<u64>) -> Searcher<'a> {
+        Searcher { pos, tt, ordering, control, quiescence, path, pv: vec![vec![]; MAX_PLY + 1], root_best: None,
+                   tablebase: None, network: None, quiet_moves: vec![0] }
+    }
+
+    // kings' moves without strikes the game ends with, for the draw limits of the rules
+    pub fn with_quiet_moves(mut self, quiet_moves: usize) -> Searcher<'a> {
+        self.quiet_moves = vec![quiet_moves];
+        self
+    }
+
+    pub fn with_tablebase(mut self, tablebase: Option<&'a Tablebase>) -> Searcher<'a> {
+        self.tablebase = tablebase;
+        self
+    }
+
+    pub fn with_network(mut self, network: Option<&'a Network>) -> Searcher<'a> {
+        self.network = network;
+        self
+    }
+
+    pub fn evaluate(&mut self) -> i32 {
+        // a position without moves is lost whatever the network says
+        if let Some(network) = self.network {
+            let move_list = self.pos.get_move_list_cached();
+            if !move_list.as_ref().as_ref().unwrap().list.is_empty() { return network.eval(self.pos); }
+        }
+        let eval = self.pos.evaluate();
+        if self.pos.next_move == Some(Color::White) { eval } else { -eval }
+    }
+
+    // score of the side to move at the leaf, a loss in `ply` plies without moves
+    fn leaf(&mut self, no_moves: bool, ply: usize) -> i32 {
+        if no_moves { ply as i32 - WIN } else { self.evaluate() }
+    }
+
+    // draw by the kings-only or the quiet move limit of the rules
+    fn quiet_draw(&self) -> bool {
+        let rules = &self.pos.environment.rules.draw_rules;
+        let limit = rules.kings_only_moves.into_iter().chain(rules.quiet_moves).min();
+        limit.is_some_and(|limit| *self.quiet_moves.last().unwrap() >= limit)
+    }
+
+    fn make(&mut self, move_item: &MoveItem) -> Vec<Option<Piece>> {
+        let quiet = move_item.strike.is_none()
+            && self.pos.cells[move_item.from()].as_ref().is_some_and(|x| x.is_king);
+        self.quiet_moves.push(if quiet { self.quiet_moves.last().unwrap() + 1 } else { 0 });
+        self.path.push(self.pos.zobrist());
+        self.pos.make_move(move_item);
+        std::mem::take(&mut self.pos.took_pieces)
+    }
+
+    fn unmake(&mut self, move_item: &MoveItem, took_pieces: Vec<Option<Piece>>) {
+        self.pos.took_pieces = took_pieces;
+        self.pos.unmake_move(move_item);
+        self.path.pop();
+        self.quiet_moves.pop();
+    }
+
+    // principal variation of the last root search, at least the best root move
+    pub fn pv(&self) -> Vec<MoveItem> {
+        if self.pv[0].is_empty() {
+            return self.root_best.iter().cloned().collect();
+        }
+        self.pv[0].clone()
+    }
+
+    pub fn root(&mut self, depth: i16, alpha: i32, beta: i32) -> i32 {
+        self.root_best = None;
+        self.negamax(depth, alpha, beta, 0)
+    }
+
+    // root search in a narrow window around `guess`, widened while the score falls outside
+    pub fn aspiration(&mut self, depth: i16, guess: i32) -> i32 {
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = guess.saturating_sub(delta).max(-INFINITY);
+        let mut beta = guess.saturating_add(delta).min(INFINITY);
+        loop {
+            let score = self.root(depth, alpha, beta);
+            if self.control.aborted { return score; }
+            if score <= alpha && alpha > -INFINITY {
+                alpha = score.saturating_sub(delta).max(-INFINITY);
+            } else if score >= beta && beta < INFINITY {
+                beta = score.saturating_add(delta).min(INFINITY);
+            } else {
+                return score;
+            }
+            delta = delta.saturating_mul(4);
+        }
+    }
+
+    // every root move searched with the full window, in move list order
+    pub fn root_moves(&mut self, mut depth: i16) -> Vec<(i32, Vec<MoveItem>)> {
+        let move_list = self.pos.get_move_list_cached();
+        let list = &move_list.as_ref().as_ref().unwrap().list;
+        if list.len() == 1 { depth += 1; }
+        list.iter().map(|move_item| self.root_move(move_item, depth, -INFINITY)).collect()
+    }
+
+    // One root move searched to beat `alpha`: the exact score and line if it does,
+    // otherwise an upper bound of the score.
+    pub fn root_move(&mut self, move_item: &MoveItem, depth: i16, alpha: i32) -> (i32, Vec<MoveItem>) {
+        let took_pieces = self.make(move_item);
+        let score = -self.negamax(depth - 1, -INFINITY, -alpha, 1);
+        self.unmake(move_item, took_pieces);
+        let mut line = vec![move_item.clone()];
+        line.extend(self.pv[1].iter().cloned());
+        (score, line)
+    }
+
+    fn negamax(&mut self, mut depth: i16, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.pv[ply].clear();
+        if self.control.tick() { return 0; }
+        let key = self.pos.zobrist();
+        if ply > 0 && (self.path.contains(&key) || self.quiet_draw()) { return 0; }
+        if ply > 0 {
+            if let Some(value) = self.tablebase.and_then(|tb| tb.probe(self.pos)) {
+                return value.score(ply);
+            }
+        }
+        let move_list = self.pos.get_move_list_cached();
+        let list = &move_list.as_ref().as_ref().unwrap().list;
+        if list.is_empty() { return self.leaf(true, ply); }
+        // forced moves don't use up depth
+        if list.len() == 1 && ply < MAX_PLY / 2 { depth += 1; }
+        if depth <= 0 || ply >= MAX_PLY { return self.quiesce(alpha, beta, 0, ply); }
+
+        let pv_node = alpha + 1 < beta;
+        let tt_entry = self.tt.probe(key).copied().map(|mut x| {
+            x.score = from_tt(x.score, ply);
+            x
+        });
+        if let Some(ref entry) = tt_entry {
+            if ply > 0 && !pv_node {
+                if let Some(score) = TranspositionTable::cutoff(entry, depth, alpha, beta) {
+                    self.control.stats.tt_hits += 1;
+                    return score;
+                }
+            }
+        }
+        let order = self.order(list, tt_entry.and_then(|x| x.best_move()), ply);
+        let alpha_orig = alpha;
+        let mut best = -INFINITY;
+        let mut best_index = None;
+        for (n, i) in order.into_iter().enumerate() {
+            let move_item = &list[i];
+            let took_pieces = self.make(move_item);
+            let score = if n == 0 {
+                -self.negamax(depth - 1, -beta, -alpha, ply + 1)
+            } else {
+                // null window: only proves the move is not better, searched again if it is
+                let score = -self.negamax(depth - 1, -alpha - 1, -alpha, ply + 1);
+                if score > alpha && score < beta {
+                    -self.negamax(depth - 1, -beta, -alpha, ply + 1)
+                } else { score }
+            };
+            self.unmake(move_item, took_pieces);
+            if self.control.aborted { return 0; }
+            if score > best {
+                best = score;
+                best_index = Some(i);
+                if ply == 0 { self.root_best = Some(move_item.clone()); }
+                if score > alpha {
+                    alpha = score;
+                    let (head, tail) = self.pv.split_at_mut(ply + 1);
+                    head[ply].clear();
+                    head[ply].push(move_item.clone());
+                    head[ply].extend(tail[0].iter().cloned());
+                }
+            }
+            if alpha >= beta {
+                self.control.stats.cutoffs += 1;
+                self.ordering.cutoff(move_item, ply, depth);
+                break;
+            }
+        }
+        let bound = TranspositionTable::bound(best, alpha_orig, beta);
+        self.tt.store(key, depth, bound, to_tt(best, ply), best_index);
+        best
+    }
+
+    // Strikes of the current position played out, static evaluation once there are none.
+    // Strikes are mandatory, so a position with strikes has no stand pat score.
+    pub fn quiesce(&mut self, mut alpha: i32, beta: i32, depth: i16, ply: usize) -> i32 {
+        if self.control.tick() { return 0; }
+        let move_list = self.pos.get_move_list_cached();
+        let list = &move_list.as_ref().as_ref().unwrap().list;
+        if !self.quiescence.enabled || depth >= self.quiescence.max_depth
+            || list.first().is_none_or(|x| x.strike.is_none()) {
+            return self.leaf(list.is_empty(), ply);
+        }
+        let mut best = -INFINITY;
+        for move_item in list {
+            let took_pieces = self.make(move_item);
+            let score = -self.quiesce(-beta, -alpha, depth + 1, ply + 1);
+            self.unmake(move_item, took_pieces);
+            if self.control.aborted { return 0; }
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta { break; }
+        }
+        best
+    }
+
+    // indices of the moves, best candidates first: the table move, then by killers and
+    // history, or by the evaluation of the children when heuristic ordering is off
+    fn order(&mut self, list: &[MoveItem], tt_move: Option<usize>, ply: usize) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..list.len()).collect();
+        if self.ordering.enabled {
+            order.sort_by_cached_key(|&i| (Some(i) != tt_move, Reverse(self.ordering.score(&list[i], ply))));
+        } else {
+            let evals: Vec<i32> = list.iter().map(|move_item| {
+                let took_pieces = self.make(move_item);
+                let eval = -self.evaluate();
+                self.unmake(move_item, took_pieces);
+                eval
+            }).collect();
+            order.sort_by_key(|&i| (Some(i) != tt_move, Reverse(evals[i])));
+        }
+        order
+    }
+}
+
+// wins and losses are stored in the table by the plies from the position, not from the root
+fn to_tt(score: i32, ply: usize) -> i32 {
+    if score > WIN_BOUND { score + ply as i32 } else if score < -WIN_BOUND { score - ply as i32 } else { score }
+}
+
+fn from_tt(score: i32, ply: usize) -> i32 {
+    if score > WIN_BOUND { score - ply as i32 } else if score < -WIN_BOUND { score + ply as i32 } else { score }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+    use crate::game::Game;
+    use crate::moves_list::MoveItem;
+    use crate::notation::Notation;
+    use crate::position::Position;
+    use crate::rules::{RuleSet, Variant};
+    use super::WIN;
+
+    // plain negamax without pruning, same leaves, extensions and repetitions as Searcher
+    fn minimax(pos: &mut Position, mut depth: i16, path: &mut Vec<u64>, ply: usize) -> i32 {
+        let key = pos.zobrist();
+        if ply > 0 && path.contains(&key) { return 0; }
+        let list = pos.get_move_list(false).list;
+        let evaluate = |pos: &mut Position| {
+            let eval = pos.evaluate();
+            if pos.next_move == Some(Color::White) { eval } else { -eval }
+        };
+        if list.is_empty() { return ply as i32 - WIN; }
+        if list.len() == 1 { depth += 1; }
+        if depth <= 0 { return evaluate(pos); }
+        let mut best = i32::MIN + 1;
+        for move_item in &list {
+            path.push(key);
+            pos.make_move(move_item);
+            let took_pieces = pos.took_pieces.clone();
+            best = best.max(-minimax(pos, depth - 1, path, ply + 1));
+            pos.took_pieces = took_pieces;
+            pos.unmake_move(move_item);
+            path.pop();
+        }
+        best
+    }
+
+    fn root_scores(pos: &mut Position, depth: i16) -> Vec<(MoveItem, i32)> {
+        let list = pos.get_move_list(false).list;
+        let depth = if list.len() == 1 { depth + 1 } else { depth };
+        list.into_iter().map(|move_item| {
+            let mut path = vec![pos.zobrist()];
+            pos.make_move(&move_item);
+            let took_pieces = pos.took_pieces.clone();
+            let score = -minimax(pos, depth - 1, &mut path, 1);
+            pos.took_pieces = took_pieces;
+            pos.unmake_move(&move_item);
+            (move_item, score)
+        }).collect()
+    }
+
+    const POSITIONS: [&str; 5] = [
+        "W:W21-32:B1-12",
+        "W:W17,21,22,23,24,25,27,28,29,30,31:B1,2,3,4,5,6,7,8,10,11,12",
+        "B:W18,21,22,24,25,26,27,29,30,31:B1,2,3,5,6,7,9,10,11,13",
+        "W:WK5,22,26,30:B9,13,15,16,K27",
+        "B:WK29,K32:BK4,12,16",
+    ];
+
+    #[test]
+    fn pvs_matches_minimax() {
+        for fen in POSITIONS {
+            for max_depth in 1..4 {
+                for heuristic in [false, true] {
+                    let mut game = Game::new(8);
+                    game.set_fen(fen).unwrap();
+                    game.set_quiescence(false, 0);
+                    game.set_heuristic_ordering(heuristic);
+                    let best = game.best_move(max_depth, i32::MIN, i32::MAX, 0);
+                    let mut pos = game.current_position.clone();
+                    let scores = root_scores(&mut pos, max_depth + 1);
+                    let top = scores.iter().map(|x| x.1).max().unwrap();
+                    let sign = if pos.next_move == Some(Color::White) { 1 } else { -1 };
+                    assert_eq!(best.deep_eval, top * sign, "{} depth {}", fen, max_depth);
+                    // the move found has the best score (the same move when the best is unique)
+                    let env = game.current_position.environment.clone();
+                    let text = best.get_move_item().text(&env, Notation::Algebraic).to_string();
+                    let best_moves: Vec<_> = scores.iter().filter(|x| x.1 == top)
+                        .map(|x| x.0.text(&env, Notation::Algebraic).to_string()).collect();
+                    assert!(best_moves.contains(&text), "{} depth {}: {} not in {:?}", fen, max_depth, text, best_moves);
+                    // the search leaves the game as it was
+                    assert_eq!(game.get_fen(), pos.to_fen());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn aspiration_matches_full_window() {
+        for fen in POSITIONS {
+            let mut game = Game::new(8);
+            game.set_fen(fen).unwrap();
+            let full = game.best_move(4, i32::MIN, i32::MAX, 0).deep_eval;
+            game.clear_tt();
+            let mut searcher = game.searcher();
+            let guess = searcher.root(3, -super::INFINITY, super::INFINITY);
+            let score = searcher.aspiration(5, guess + 1000);
+            let sign = if game.current_position.next_move == Some(Color::White) { 1 } else { -1 };
+            assert_eq!(score * sign, full, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn losses_by_plies() {
+        let mut game = Game::new(8);
+        game.set_fen("W:W22:B18").unwrap();
+        assert_eq!(game.best_move(4, i32::MIN, i32::MAX, 0).deep_eval, WIN - 1);
+        game.set_fen("B:W22:B18").unwrap();
+        assert_eq!(game.best_move(4, i32::MIN, i32::MAX, 0).deep_eval, 1 - WIN);
+    }
+
+    #[test]
+    fn quiet_moves_draw() {
+        let play = |rules: RuleSet| {
+            let mut game = Game::with_rules(rules);
+            game.set_fen("W:WK29,K25:BK4").unwrap();
+            let moves = ["25-22", "4-8", "22-25", "8-4"];
+            for ply in 0..8 {
+                let move_item = game.find_move(moves[ply % 4]).unwrap();
+                game.make_move_by_move_item(&move_item);
+            }
+            game.best_move(3, i32::MIN, i32::MAX, 0).deep_eval
+        };
+        let mut rules = RuleSet::new(Variant::English);
+        rules.draw_rules.repetitions = u8::MAX;
+        rules.draw_rules.quiet_moves = Some(9);
+        // any move of white is the 9th quiet one
+        assert_eq!(play(rules.clone()), 0);
+        rules.draw_rules.quiet_moves = None;
+        assert!(play(rules) > 0);
+    }
+}
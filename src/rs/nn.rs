@@ -0,0 +1,346 @@
+use std::io;
+use std::io::{Read, Write};
+use crate::color::Color;
+use crate::features::{encode, input_len, policy_index, policy_len};
+use crate::mcts::EVAL_SCALE;
+use crate::position::Position;
+use crate::puct::{EvalRequest, Evaluation, Evaluator};
+
+const MAGIC: &[u8; 4] = b"CKNN";
+const VERSION: u8 = 1;
+// values of a layer read from a file, against corrupt sizes
+const MAX_WEIGHTS: usize = 1 << 26;
+// the values kept off 1 so that their evaluation stays finite
+const MAX_VALUE: f32 = 0.9999;
+
+// Layer of a network, weights in the row-major order of their shape
+#[derive(Clone, Debug, PartialEq)]
+pub enum Layer {
+    // outputs x inputs weights
+    Dense { inputs: usize, outputs: usize, weights: Vec<f32>, bias: Vec<f32> },
+    // filters x channels x kernel x kernel weights moved over the planes of the board,
+    // zero padded so that the planes keep their size
+    Conv { channels: usize, filters: usize, kernel: usize, weights: Vec<f32>, bias: Vec<f32> },
+    Relu,
+}
+
+impl Layer {
+    // outputs for `len` inputs on a board of `size`, None if the layer doesn't take them
+    fn output_len(&self, size: usize, len: usize) -> Option<usize> {
+        match self {
+            Layer::Dense { inputs, outputs, weights, bias } => {
+                (len == *inputs && weights.len() == inputs * outputs && bias.len() == *outputs).then_some(*outputs)
+            }
+            Layer::Conv { channels, filters, kernel, weights, bias } => {
+                (len == channels * size * size && kernel % 2 == 1
+                    && weights.len() == filters * channels * kernel * kernel && bias.len() == *filters)
+                    .then_some(filters * size * size)
+            }
+            Layer::Relu => Some(len),
+        }
+    }
+
+    fn forward(&self, size: usize, input: &[f32]) -> Vec<f32> {
+        match self {
+            Layer::Dense { inputs, weights, bias, .. } => {
+                bias.iter().zip(weights.chunks(*inputs)).map(|(b, row)| {
+                    b + row.iter().zip(input).map(|(w, x)| w * x).sum::<f32>()
+                }).collect()
+            }
+            Layer::Conv { channels, filters, kernel, weights, bias } => {
+                let area = size * size;
+                let half = kernel / 2;
+                let mut output = vec![0.0; filters * area];
+                for (f, out) in output.chunks_mut(area).enumerate() {
+                    out.fill(bias[f]);
+                    for c in 0..*channels {
+                        let plane = &input[c * area..(c + 1) * area];
+                        for ky in 0..*kernel {
+                            for kx in 0..*kernel {
+                                let w = weights[((f * channels + c) * kernel + ky) * kernel + kx];
+                                if w == 0.0 { continue; }
+                                // rows and columns of the output that read inside the board
+                                let ys = half.saturating_sub(ky)..(size + half).saturating_sub(ky).min(size);
+                                for y in ys {
+                                    let sy = y + ky - half;
+                                    for x in half.saturating_sub(kx)..(size + half).saturating_sub(kx).min(size) {
+                                        out[y * size + x] += w * plane[sy * size + x + kx - half];
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                output
+            }
+            Layer::Relu => input.iter().map(|x| x.max(0.0)).collect(),
+        }
+    }
+}
+
+// Network evaluating positions from the planes of features::encode, on the CPU. The first
+// output is the value for white before tanh, the others, if any, the policy logits of the
+// moves as numbered by features::policy_index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Network {
+    size: usize,
+    layers: Vec<Layer>,
+}
+
+impl Network {
+    pub fn new(size: usize, layers: Vec<Layer>) -> Result<Network, String> {
+        let mut len = input_len(size);
+        for (i, layer) in layers.iter().enumerate() {
+            len = layer.output_len(size, len).ok_or_else(|| format!("layer {} doesn't take {} inputs", i, len))?;
+        }
+        if len != 1 && len != 1 + policy_len(size) {
+            return Err(format!("{} outputs, expected 1 or {}", len, 1 + policy_len(size)));
+        }
+        Ok(Network { size, layers })
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn has_policy(&self) -> bool {
+        let len = self.layers.iter().fold(input_len(self.size), |len, x| x.output_len(self.size, len).unwrap());
+        len > 1
+    }
+
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        assert_eq!(input.len(), input_len(self.size), "input of a board of another size");
+        let mut values = input.to_vec();
+        for layer in &self.layers {
+            values = layer.forward(self.size, &values);
+        }
+        values
+    }
+
+    // value for the side to move from -1 to 1, and the outputs of the network
+    fn run(&self, pos: &Position) -> (f32, Vec<f32>) {
+        let outputs = self.forward(&encode(pos));
+        let value = outputs[0].tanh();
+        (if pos.next_move == Some(Color::White) { value } else { -value }, outputs)
+    }
+
+    pub fn value(&self, pos: &Position) -> f32 {
+        self.run(pos).0
+    }
+
+    // value for the side to move in the scale of the static evaluation, for alpha-beta
+    pub fn eval(&self, pos: &Position) -> i32 {
+        let value = self.value(pos).clamp(-MAX_VALUE, MAX_VALUE) as f64;
+        (value.atanh() * EVAL_SCALE) as i32
+    }
+
+    // softmax of the logits of the moves, uniform without a policy
+    pub fn evaluate_request(&self, request: &EvalRequest) -> Evaluation {
+        let (value, outputs) = self.run(&request.pos);
+        let moves = request.moves.len();
+        if outputs.len() == 1 || moves == 0 {
+            return Evaluation { policy: vec![1.0 / moves.max(1) as f32; moves], value };
+        }
+        let logits: Vec<f32> = request.moves.iter().map(|x| outputs[1 + policy_index(&request.pos, x)]).collect();
+        let max = logits.iter().fold(f32::NEG_INFINITY, |a, b| a.max(*b));
+        let exp: Vec<f32> = logits.iter().map(|x| (x - max).exp()).collect();
+        let sum: f32 = exp.iter().sum();
+        Evaluation { policy: exp.iter().map(|x| x / sum).collect(), value }
+    }
+
+    // header, then every layer as its kind, its dimensions and its weights and biases
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION, self.size as u8])?;
+        w.write_all(&(self.layers.len() as u32).to_le_bytes())?;
+        let floats = |w: &mut dyn Write, values: &[f32]| -> io::Result<()> {
+            values.iter().try_for_each(|x| w.write_all(&x.to_le_bytes()))
+        };
+        for layer in &self.layers {
+            match layer {
+                Layer::Dense { inputs, outputs, weights, bias } => {
+                    w.write_all(&[0])?;
+                    w.write_all(&(*inputs as u32).to_le_bytes())?;
+                    w.write_all(&(*outputs as u32).to_le_bytes())?;
+                    floats(w, weights)?;
+                    floats(w, bias)?;
+                }
+                Layer::Conv { channels, filters, kernel, weights, bias } => {
+                    w.write_all(&[1])?;
+                    for x in [channels, filters, kernel] {
+                        w.write_all(&(*x as u32).to_le_bytes())?;
+                    }
+                    floats(w, weights)?;
+                    floats(w, bias)?;
+                }
+                Layer::Relu => w.write_all(&[2])?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Network> {
+        let invalid = |text: &str| io::Error::new(io::ErrorKind::InvalidData, text.to_string());
+        let mut header = [0u8; 10];
+        r.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC || header[4] != VERSION { return Err(invalid("not a network file")); }
+        let size = header[5] as usize;
+        let count = u32::from_le_bytes(header[6..10].try_into().unwrap());
+        let u32s = |r: &mut dyn Read, n: usize| -> io::Result<Vec<usize>> {
+            let mut bytes = vec![0u8; 4 * n];
+            r.read_exact(&mut bytes)?;
+            Ok(bytes.chunks(4).map(|x| u32::from_le_bytes(x.try_into().unwrap()) as usize).collect())
+        };
+        let floats = |r: &mut dyn Read, n: usize| -> io::Result<Vec<f32>> {
+            if n > MAX_WEIGHTS { return Err(invalid("layer too large")); }
+            let mut bytes = vec![0u8; 4 * n];
+            r.read_exact(&mut bytes)?;
+            Ok(bytes.chunks(4).map(|x| f32::from_le_bytes(x.try_into().unwrap())).collect())
+        };
+        let mut layers = vec![];
+        for _ in 0..count {
+            let mut kind = [0u8];
+            r.read_exact(&mut kind)?;
+            layers.push(match kind[0] {
+                0 => {
+                    let dims = u32s(r, 2)?;
+                    let (inputs, outputs) = (dims[0], dims[1]);
+                    let weights = floats(r, inputs.saturating_mul(outputs))?;
+                    Layer::Dense { inputs, outputs, weights, bias: floats(r, outputs)? }
+                }
+                1 => {
+                    let dims = u32s(r, 3)?;
+                    let (channels, filters, kernel) = (dims[0], dims[1], dims[2]);
+                    let weights = floats(r, filters.saturating_mul(channels).saturating_mul(kernel.saturating_mul(kernel)))?;
+                    Layer::Conv { channels, filters, kernel, weights, bias: floats(r, filters)? }
+                }
+                2 => Layer::Relu,
+                _ => return Err(invalid("unknown layer")),
+            });
+        }
+        Network::new(size, layers).map_err(|err| invalid(&err))
+    }
+}
+
+impl Evaluator for Network {
+    fn evaluate(&mut self, requests: &[EvalRequest]) -> Vec<Evaluation> {
+        requests.iter().map(|x| self.evaluate_request(x)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::features::{input_len, policy_index, policy_len, PLANES};
+    use crate::game::Game;
+    use crate::mcts::McTree;
+    use crate::nn::{Layer, Network};
+    use crate::puct::{EvalRequest, Evaluator, PuctConfig};
+
+    // a tenth of the material of white, kings worth three men
+    fn material() -> Network {
+        let mut weights = vec![0.0; input_len(8)];
+        for (plane, worth) in [0.1, 0.3, -0.1, -0.3].iter().enumerate() {
+            weights[plane * 64..(plane + 1) * 64].fill(*worth);
+        }
+        Network::new(8, vec![Layer::Dense { inputs: input_len(8), outputs: 1, weights, bias: vec![0.0] }]).unwrap()
+    }
+
+    #[test]
+    fn forward() {
+        // the plane of the white men moved a square up and right, then summed
+        let mut weights = vec![0.0; PLANES * 9];
+        weights[0] = 1.0;
+        let network = Network::new(8, vec![
+            Layer::Conv { channels: PLANES, filters: 1, kernel: 3, weights, bias: vec![0.0] },
+            Layer::Relu,
+            Layer::Dense { inputs: 64, outputs: 1, weights: vec![1.0; 64], bias: vec![0.5] },
+        ]).unwrap();
+        assert!(!network.has_policy());
+        let mut game = Game::new(8);
+        // g1 moves to h2, b8 off the board
+        game.set_fen("W:W1,32:B5").unwrap();
+        let input = crate::features::encode(&game.current_position);
+        assert_eq!(network.forward(&input), vec![1.5]);
+        // a centred kernel keeps the planes
+        let mut weights = vec![0.0; 9];
+        weights[4] = 2.0;
+        let layer = Layer::Conv { channels: 1, filters: 1, kernel: 3, weights, bias: vec![-1.0] };
+        let output = layer.forward(8, &input[..64]);
+        assert_eq!(output.iter().filter(|x| **x == 1.0).count(), 2);
+        assert_eq!(output.iter().filter(|x| **x == -1.0).count(), 62);
+    }
+
+    #[test]
+    fn values() {
+        let network = material();
+        let mut game = Game::new(8);
+        game.set_fen("W:WK1,21:B12").unwrap();
+        let value = network.value(&game.current_position);
+        assert!((value - 0.3f32.tanh()).abs() < 1e-6);
+        game.set_fen("B:WK1,21:B12").unwrap();
+        assert!((network.value(&game.current_position) + value).abs() < 1e-6);
+        assert!((network.eval(&game.current_position) + 300).abs() <= 1);
+        // no policy: uniform priors
+        game.set_start_position();
+        let moves = game.current_position.get_move_list(false).list;
+        let request = EvalRequest { pos: game.current_position.clone(), moves: moves.clone() };
+        let evaluation = network.clone().evaluate(&[request.clone()]).remove(0);
+        assert_eq!(evaluation.policy, vec![1.0 / 7.0; 7]);
+        assert!(evaluation.value.abs() < 1e-6);
+        // a policy preferring the third move
+        let mut bias = vec![0.0; 1 + policy_len(8)];
+        bias[1 + policy_index(&game.current_position, &moves[2])] = 3.0;
+        let weights = vec![0.0; input_len(8) * bias.len()];
+        let network = Network::new(8, vec![Layer::Dense { inputs: input_len(8), outputs: bias.len(), weights, bias }])
+            .unwrap();
+        assert!(network.has_policy());
+        let policy = network.evaluate_request(&request).policy;
+        assert!((policy.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!((policy[2] / policy[0] - 3f32.exp()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let mut weights = vec![0.0; PLANES * 4 * 9];
+        weights.iter_mut().enumerate().for_each(|(i, x)| *x = i as f32 / 100.0);
+        let network = Network::new(8, vec![
+            Layer::Conv { channels: PLANES, filters: 4, kernel: 3, weights, bias: vec![0.1, 0.2, 0.3, 0.4] },
+            Layer::Relu,
+            Layer::Dense { inputs: 256, outputs: 1, weights: vec![-0.5; 256], bias: vec![1.0] },
+        ]).unwrap();
+        let mut bytes = vec![];
+        network.write(&mut bytes).unwrap();
+        assert_eq!(Network::read(&mut &bytes[..]).unwrap(), network);
+        assert!(Network::read(&mut &bytes[..bytes.len() - 1]).is_err());
+        bytes[0] = b'X';
+        assert!(Network::read(&mut &bytes[..]).is_err());
+        // layers that don't fit
+        assert!(Network::new(8, vec![Layer::Dense { inputs: 10, outputs: 1, weights: vec![0.0; 10], bias: vec![0.0] }])
+            .is_err());
+        assert!(Network::new(8, vec![Layer::Relu]).is_err());
+    }
+
+    #[test]
+    fn search() {
+        let network = Arc::new(material());
+        let mut game = Game::new(8);
+        game.set_start_position();
+        game.set_network(Some(network.clone()));
+        assert_eq!(game.searcher().evaluate(), network.eval(&game.current_position));
+        // 22x15 takes the last piece, a lost position whatever the network says
+        game.set_fen("W:WK22,K29:B18").unwrap();
+        let best = game.best_move(3, i32::MIN, i32::MAX, 0);
+        assert!(best.deep_eval > 1_000_000);
+        // and as the evaluator of PUCT
+        let mut tree = McTree::new(game.current_position.clone(), game.position_history.clone());
+        let best = tree.search_puct(&mut material(), &PuctConfig::default(), 64, &mut StdRng::seed_from_u64(4));
+        assert_eq!(best.unwrap().borrow().value(), 1.0);
+    }
+}
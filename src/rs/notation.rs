@@ -0,0 +1,240 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::moves::BoardPos;
+use crate::moves_list::{MoveItem, MoveList};
+use crate::position_environment::PositionEnvironment;
+
+// square numbering used in text formats: PDN numbers (1 at the top left, black side)
+// or algebraic (a1 at the bottom left, white side)
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Notation {
+    Numeric,
+    Algebraic,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotationError(pub String);
+
+impl Display for NotationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for NotationError {}
+
+impl PositionEnvironment {
+    fn half(&self) -> usize {
+        self.size as usize / 2
+    }
+
+    pub fn pack_to_numeric(&self, pos: BoardPos) -> usize {
+        let (row, k) = (pos / self.half(), pos % self.half());
+        (self.size as usize - 1 - row) * self.half() + k + 1
+    }
+
+    pub fn numeric_to_pack(&self, n: usize) -> Option<BoardPos> {
+        if n == 0 || n > self.pack_to_board.len() { return None; }
+        let (q, k) = ((n - 1) / self.half(), (n - 1) % self.half());
+        Some((self.size as usize - 1 - q) * self.half() + k)
+    }
+
+    pub fn pack_to_algebraic(&self, pos: BoardPos) -> String {
+        let i = self.pack_to_board[pos];
+        let size = self.size as usize;
+        format!("{}{}", (b'a' + (i % size) as u8) as char, i / size + 1)
+    }
+
+    pub fn algebraic_to_pack(&self, square: &str) -> Option<BoardPos> {
+        let mut chars = square.chars();
+        let file = chars.next()?.to_ascii_lowercase();
+        let rank: usize = chars.as_str().parse().ok()?;
+        let size = self.size as usize;
+        if !file.is_ascii_lowercase() || rank == 0 || rank > size { return None; }
+        let col = file as usize - 'a' as usize;
+        if col >= size { return None; }
+        let i = (rank - 1) * size + col;
+        // light squares are not packed
+        if self.pack_to_board[self.board_to_pack[i]] != i { return None; }
+        Some(self.board_to_pack[i])
+    }
+
+    pub fn square_to_string(&self, pos: BoardPos, notation: Notation) -> String {
+        match notation {
+            Notation::Numeric => self.pack_to_numeric(pos).to_string(),
+            Notation::Algebraic => self.pack_to_algebraic(pos),
+        }
+    }
+
+    // accepts both numeric and algebraic squares
+    pub fn parse_square(&self, square: &str) -> Result<BoardPos, NotationError> {
+        let square = square.trim();
+        let pos = if square.chars().all(|x| x.is_ascii_digit()) {
+            square.parse().ok().and_then(|n| self.numeric_to_pack(n))
+        } else {
+            self.algebraic_to_pack(square)
+        };
+        pos.ok_or_else(|| NotationError(format!("invalid square '{}'", square)))
+    }
+
+    // squares of a move written with '-', ':' or 'x' separators
+    pub fn parse_move_squares(&self, text: &str) -> Result<Vec<BoardPos>, NotationError> {
+        let text = text.trim();
+        let squares = text.split(['-', ':', 'x'])
+            .map(|x| self.parse_square(x))
+            .collect::<Result<Vec<_>, _>>()?;
+        if squares.len() < 2 {
+            return Err(NotationError(format!("invalid move '{}'", text)));
+        }
+        Ok(squares)
+    }
+}
+
+// "c3-d4" for quiet moves, every landing square for strikes: "c3:e5:g3"
+pub struct MoveText<'a> {
+    move_item: &'a MoveItem,
+    environment: &'a PositionEnvironment,
+    notation: Notation,
+}
+
+impl Display for MoveText<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let path = self.move_item.path();
+        let separator = if self.move_item.strike.is_some() { ":" } else { "-" };
+        for (i, pos) in path.iter().enumerate() {
+            if i > 0 { write!(f, "{}", separator)?; }
+            write!(f, "{}", self.environment.square_to_string(*pos, self.notation))?;
+        }
+        Ok(())
+    }
+}
+
+impl MoveItem {
+    pub fn text<'a>(&'a self, environment: &'a PositionEnvironment, notation: Notation) -> MoveText<'a> {
+        MoveText { move_item: self, environment, notation }
+    }
+
+    // start square followed by every landing square
+    pub fn path(&self) -> Vec<BoardPos> {
+        let mut path = vec![self.from()];
+        match &self.strike {
+            Some(strike) => strike.vec.iter().for_each(|x| path.push(x.to)),
+            None => path.push(self.to())
+        }
+        path
+    }
+
+    fn taken(&self) -> Vec<BoardPos> {
+        let mut taken: Vec<_> = self.strike.iter().flat_map(|x| x.vec.iter().map(|x| x.take)).collect();
+        taken.sort();
+        taken
+    }
+}
+
+impl MoveList {
+    // Finds the legal move written as `text`. Intermediate landing squares may be left out
+    // as long as all matching moves take the same pieces; otherwise the text is ambiguous.
+    pub fn find_move(&self, environment: &PositionEnvironment, text: &str) -> Result<&MoveItem, NotationError> {
+        let squares = environment.parse_move_squares(text)?;
+        // "-" between the squares of quiet moves, ":" or "x" for strikes
+        let strike = text.contains([':', 'x']);
+        if strike && text.contains('-') {
+            return Err(NotationError(format!("invalid move '{}'", text.trim())));
+        }
+        let is_subsequence = |path: &Vec<BoardPos>| {
+            let mut rest = path[1..path.len() - 1].iter();
+            squares[1..squares.len() - 1].iter().all(|x| rest.any(|y| y == x))
+        };
+        let found: Vec<_> = self.list.iter().filter(|x| {
+            let path = x.path();
+            x.strike.is_some() == strike && path[0] == squares[0] && path[path.len() - 1] == squares[squares.len() - 1]
+                && is_subsequence(&path)
+        }).collect();
+        let first = match found.first() {
+            Some(x) => *x,
+            None => return Err(NotationError(format!("illegal move '{}'", text)))
+        };
+        if let Some(exact) = found.iter().find(|x| x.path() == squares) {
+            return Ok(exact);
+        }
+        if found.iter().any(|x| x.taken() != first.taken() || x.is_king_move() != first.is_king_move()) {
+            let variants: Vec<_> = found.iter()
+                .map(|x| x.text(environment, Notation::Algebraic).to_string()).collect();
+            return Err(NotationError(format!("ambiguous move '{}': {}", text, variants.join(", "))));
+        }
+        Ok(first)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::notation::Notation;
+    use crate::position::Position;
+    use crate::position_environment::PositionEnvironment;
+
+    #[test]
+    fn move_text() {
+        let env = Arc::new(PositionEnvironment::new(8));
+        let mut pos = Position::start_position(env.clone());
+        let list = pos.get_move_list(false);
+        assert_eq!(list.list[0].text(&env, Notation::Algebraic).to_string(), "a3-b4");
+        assert_eq!(list.list[0].text(&env, Notation::Numeric).to_string(), "21-17");
+        for move_item in &list.list {
+            for notation in [Notation::Numeric, Notation::Algebraic] {
+                let text = move_item.text(&env, notation).to_string();
+                assert_eq!(list.find_move(&env, &text).unwrap().path(), move_item.path());
+            }
+        }
+        assert!(list.find_move(&env, "a3-b2").is_err());
+        // the separator has to match the kind of move
+        assert!(list.find_move(&env, "c3:d4").is_err());
+        assert!(list.find_move(&env, "c3xd4").is_err());
+        let mut pos = Position::from_fen(env.clone(), "W:W22:B18").unwrap();
+        let list = pos.get_move_list(true);
+        assert!(list.find_move(&env, "22x15").is_ok());
+        assert!(list.find_move(&env, "22-15").is_err());
+        assert!(list.find_move(&env, "22-18:15").is_err());
+    }
+
+    #[test]
+    fn ambiguous_strike() {
+        let env = Arc::new(PositionEnvironment::new(8));
+        let mut pos = Position::from_fen(env.clone(), "W:Wf6:Bc7,e7,e5,e3,d2").unwrap();
+        let list = pos.get_move_list(true);
+        let err = list.find_move(&env, "f6:f2").unwrap_err();
+        assert!(err.0.starts_with("ambiguous move"), "{}", err);
+        let short = list.find_move(&env, "f6:d4:f2").unwrap();
+        assert_eq!(short.text(&env, Notation::Algebraic).to_string(), "f6:d4:f2");
+        let long = list.find_move(&env, "f6xd8xf2").unwrap();
+        assert_eq!(long.strike.as_ref().unwrap().vec.len(), 3);
+        assert!(long.is_king_move());
+        assert_eq!(list.find_move(&env, "11x18x27").unwrap().path(), short.path());
+    }
+
+    #[test]
+    fn squares() {
+        let env = PositionEnvironment::new(8);
+        assert_eq!(env.pack_to_numeric(0), 29);
+        assert_eq!(env.pack_to_numeric(31), 4);
+        assert_eq!(env.pack_to_algebraic(0), "a1");
+        assert_eq!(env.pack_to_algebraic(31), "h8");
+        assert_eq!(env.parse_square("c3"), Ok(env.board_to_pack[18]));
+        assert!(env.parse_square("b1").is_err());
+        assert!(env.parse_square("33").is_err());
+        for size in [8, 10] {
+            let env = PositionEnvironment::new(size);
+            for pos in 0..env.pack_to_board.len() {
+                for notation in [Notation::Numeric, Notation::Algebraic] {
+                    assert_eq!(env.parse_square(&env.square_to_string(pos, notation)), Ok(pos));
+                }
+            }
+        }
+        let env = PositionEnvironment::new(10);
+        assert_eq!(env.square_to_string(0, Notation::Numeric), "46");
+        assert_eq!(env.square_to_string(49, Notation::Algebraic), "j10");
+    }
+}
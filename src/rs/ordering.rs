@@ -0,0 +1,105 @@
+use crate::moves::BoardPos;
+use crate::moves_list::MoveItem;
+
+const KILLERS: usize = 2;
+
+// Move ordering for best_move without evaluating the children: strikes by the number of
+// pieces taken, then the killer moves of the ply, then quiet moves by their history score.
+#[derive(Clone, Debug)]
+pub struct MoveOrdering {
+    // off: children are evaluated and sorted by the evaluation
+    pub enabled: bool,
+    killers: Vec<[Option<(BoardPos, BoardPos)>; KILLERS]>,
+    // indexed by from * cells + to
+    history: Vec<u32>,
+    cells: usize,
+}
+
+impl MoveOrdering {
+    pub fn new(cells: usize) -> MoveOrdering {
+        MoveOrdering { enabled: true, killers: vec![], history: vec![0; cells * cells], cells }
+    }
+
+    // killers are kept for one search only, history scores fade out
+    pub fn new_search(&mut self) {
+        self.killers.clear();
+        self.history.iter_mut().for_each(|x| *x /= 2);
+    }
+
+    pub fn score(&self, move_item: &MoveItem, ply: usize) -> u32 {
+        if let Some(ref strike) = move_item.strike {
+            return (1 << 30) + strike.vec.len() as u32;
+        }
+        let key = Some((move_item.from(), move_item.to()));
+        if let Some(i) = self.killers.get(ply).and_then(|x| x.iter().position(|x| *x == key)) {
+            return (1 << 29) - i as u32;
+        }
+        self.history[move_item.from() * self.cells + move_item.to()]
+    }
+
+    // a quiet move that cut the search at `ply` with `draft` plies below it
+    pub fn cutoff(&mut self, move_item: &MoveItem, ply: usize, draft: i16) {
+        if move_item.strike.is_some() { return; }
+        let key = Some((move_item.from(), move_item.to()));
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; KILLERS]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != key {
+            killers.copy_within(0..KILLERS - 1, 1);
+            killers[0] = key;
+        }
+        let history = &mut self.history[move_item.from() * self.cells + move_item.to()];
+        let bonus = (draft.max(1) as u32).pow(2);
+        *history = (*history + bonus).min((1 << 28) - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::moves::QuietMove;
+    use crate::moves_list::MoveItem;
+    use crate::ordering::MoveOrdering;
+
+    fn quiet(from: usize, to: usize) -> MoveItem {
+        MoveItem { strike: None, mov: Some(QuietMove { from, to, king_move: false }) }
+    }
+
+    #[test]
+    fn killers_and_history() {
+        let mut ordering = MoveOrdering::new(32);
+        let (a, b, c) = (quiet(8, 12), quiet(9, 13), quiet(10, 14));
+        ordering.cutoff(&a, 2, 3);
+        ordering.cutoff(&b, 2, 1);
+        ordering.cutoff(&c, 3, 4);
+        // killers of ply 2: b, then a
+        assert!(ordering.score(&b, 2) > ordering.score(&a, 2));
+        assert!(ordering.score(&a, 2) > ordering.score(&c, 2));
+        // history of other plies: c (16) over a (9) over b (1)
+        assert_eq!(ordering.score(&c, 1), 16);
+        assert_eq!(ordering.score(&a, 1), 9);
+        ordering.new_search();
+        assert_eq!(ordering.score(&b, 2), 0);
+        assert_eq!(ordering.score(&c, 1), 8);
+    }
+
+    #[test]
+    fn same_score_fewer_nodes() {
+        let fens = ["W:W21-32:B1-12", "W:W17,21,22,23,24,25,27,28,29,30,31:B1,2,3,4,5,6,7,8,10,11,12",
+                    "B:W18,21,22,24,25,26,27,29,30,31:B1,2,3,5,6,7,9,10,11,13"];
+        let mut nodes = [0, 0];
+        for fen in fens {
+            let mut evals = vec![];
+            for (i, heuristic) in [false, true].into_iter().enumerate() {
+                let mut game = Game::new(8);
+                game.set_fen(fen).unwrap();
+                game.set_heuristic_ordering(heuristic);
+                evals.push(game.best_move(4, i32::MIN, i32::MAX, 0).deep_eval);
+                nodes[i] += game.search_stats().nodes;
+            }
+            assert_eq!(evals[0], evals[1], "{}", fen);
+        }
+        assert!(nodes[1] < nodes[0], "{:?}", nodes);
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+use std::sync::Arc;
+use std::fmt::{Display, Formatter};
+use crate::color::Color;
+use crate::game::Game;
+use crate::moves_list::MoveItem;
+use crate::notation::{Notation, NotationError};
+use crate::position::Position;
+use crate::position_environment::PositionEnvironment;
+use crate::PositionHistory::{FinishType, PositionHistory};
+use crate::rules::RuleSet;
+use crate::ordering::MoveOrdering;
+
+// a PDN game record: tag pairs, moves as written and the result token
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PdnGame {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    pub result: Option<String>,
+}
+
+const RESULTS: [&str; 5] = ["2-0", "0-2", "1-1", "1-0", "0-1"];
+
+impl PdnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|x| x.0.eq_ignore_ascii_case(name)).map(|x| x.1.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|x| x.0.eq_ignore_ascii_case(name)) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<PdnGame, NotationError> {
+        let mut game = PdnGame::default();
+        let mut rest = text;
+        loop {
+            rest = rest.trim_start();
+            if let Some(tail) = rest.strip_prefix('[') {
+                let end = tail.find(']').ok_or_else(|| NotationError("unclosed tag".to_string()))?;
+                let (name, value) = tail[..end].trim().split_once(' ')
+                    .ok_or_else(|| NotationError(format!("invalid tag [{}]", &tail[..end])))?;
+                game.tags.push((name.to_string(), value.trim().trim_matches('"').to_string()));
+                rest = &tail[end + 1..];
+            } else if let Some(tail) = rest.strip_prefix('{') {
+                let end = tail.find('}').ok_or_else(|| NotationError("unclosed comment".to_string()))?;
+                rest = &tail[end + 1..];
+            } else {
+                break;
+            }
+        }
+        let mut comment = false;
+        for token in rest.split_whitespace() {
+            if comment || token.starts_with('{') {
+                comment = !token.ends_with('}');
+                continue;
+            }
+            if token == "*" || RESULTS.contains(&token) {
+                game.result = Some(token.to_string());
+                break;
+            }
+            // move numbers "12." or "12..." may be glued to the move
+            let token = token.trim_start_matches(|x: char| x.is_ascii_digit() && token.contains('.'))
+                .trim_start_matches('.');
+            if token.is_empty() { continue; }
+            game.moves.push(token.trim_end_matches(['!', '?', '*']).to_string());
+        }
+        if game.result.is_none() {
+            game.result = game.tag("Result").map(|x| x.to_string());
+        }
+        Ok(game)
+    }
+
+    // games of a file, each starts with its tags
+    pub fn parse_all(text: &str) -> Result<Vec<PdnGame>, NotationError> {
+        let mut chunks: Vec<String> = vec![];
+        let mut moves = true;
+        for line in text.lines() {
+            let tag = line.trim_start().starts_with('[');
+            if chunks.is_empty() || (tag && moves) { chunks.push(String::new()); }
+            if !line.trim().is_empty() { moves = !tag; }
+            let chunk = chunks.last_mut().unwrap();
+            chunk.push_str(line);
+            chunk.push('\n');
+        }
+        chunks.iter().filter(|x| !x.trim().is_empty()).map(|x| PdnGame::parse(x)).collect()
+    }
+}
+
+impl Display for PdnGame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+        let black_first = self.tag("FEN").is_some_and(|x| x.trim_start().starts_with('B'));
+        let mut tokens = vec![];
+        for (i, mov) in self.moves.iter().enumerate() {
+            let ply = i + black_first as usize;
+            let white_move = ply & 1 == 0;
+            if white_move {
+                tokens.push(format!("{}.", ply / 2 + 1));
+            } else if i == 0 {
+                tokens.push(format!("{}...", ply / 2 + 1));
+            }
+            tokens.push(mov.clone());
+        }
+        tokens.push(self.result.clone().unwrap_or_else(|| "*".to_string()));
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                writeln!(f, "{}", line)?;
+                line.clear();
+            }
+            if !line.is_empty() { line.push(' '); }
+            line += &token;
+        }
+        writeln!(f, "{}", line)
+    }
+}
+
+pub fn result_token(finish: &Option<FinishType>) -> &'static str {
+    match finish {
+        Some(FinishType::WhiteWin) => "2-0",
+        Some(FinishType::BlackWin) => "0-2",
+        Some(_) => "1-1",
+        None => "*"
+    }
+}
+
+impl Game {
+    // position before the first move of the history, found by unmaking the moves
+    pub fn initial_position(&self) -> Position {
+        let mut pos = self.current_position.clone();
+        for pos_mov in self.position_history.borrow().iter().rev() {
+            let pos_mov = pos_mov.borrow();
+            if let Some(ref mov) = pos_mov.mov {
+                pos.took_pieces = pos_mov.pos.took_pieces.clone();
+                pos.unmake_move(mov);
+            }
+        }
+        pos
+    }
+
+    pub fn to_pdn_game(&self, notation: Notation) -> PdnGame {
+        let environment = self.current_position.environment.clone();
+        let mut pdn = PdnGame { tags: self.pdn_tags.clone(), ..PdnGame::default() };
+        let rules = &environment.rules;
+        // the extended form "25,W,10,10,N2,0" when the board isn't the one of the game type
+        let game_type = if rules.size == RuleSet::new(rules.variant).size {
+            rules.pdn_game_type.to_string()
+        } else {
+            let color = if rules.first_move == Color::White { "W" } else { "B" };
+            let squares = if notation == Notation::Algebraic { "A0" } else { "N2" };
+            format!("{},{},{},{},{},0", rules.pdn_game_type, color, rules.size, rules.size, squares)
+        };
+        pdn.set_tag("GameType", &game_type);
+        let initial = self.initial_position();
+        if initial != Position::start_position(environment.clone()) {
+            pdn.set_tag("FEN", &initial.to_fen_with(notation));
+        }
+        pdn.moves = self.position_history.borrow().iter()
+            .filter_map(|x| x.borrow().mov.clone())
+            .map(|x| x.text(&environment, notation).to_string())
+            .collect();
+        let result = result_token(&self.position_history.borrow_mut().finish_check());
+        pdn.set_tag("Result", result);
+        pdn.result = Some(result.to_string());
+        pdn
+    }
+
+    // Sets up the FEN tag (or the start position) and replays the moves on a scratch game,
+    // the game is only changed once every move is legal.
+    pub fn load_pdn_game(&mut self, pdn: &PdnGame) -> Result<(), NotationError> {
+        // "25" or the extended form "25,W,8,8,A0,0" with the size of the board
+        let game_type: Vec<&str> = pdn.tag("GameType").map_or(vec![], |x| x.split(',').map(str::trim).collect());
+        let mut environment = self.position_environment.clone();
+        if let Some(rules) = game_type.first().and_then(|x| x.parse().ok()).and_then(RuleSet::from_pdn_game_type) {
+            let rules = match game_type.get(2) {
+                Some(size) => {
+                    let size = size.parse::<i8>().ok().filter(|x| x % 2 == 0 && (4..=16).contains(x))
+                        .ok_or_else(|| NotationError(format!("unsupported board size '{}'", size)))?;
+                    RuleSet { size, ..rules }
+                }
+                None => rules
+            };
+            if rules != environment.rules {
+                let mut changed = PositionEnvironment::with_rules(rules);
+                changed.eval = environment.eval.clone();
+                environment = Arc::new(changed);
+            }
+        }
+        let position = match pdn.tag("FEN") {
+            Some(fen) => Position::from_fen(environment.clone(), fen)?,
+            None => Position::start_position(environment.clone())
+        };
+        let black_first = position.next_move == Some(Color::Black);
+        let mut scratch = Game::with_rules(environment.rules.clone());
+        scratch.position_environment = environment.clone();
+        scratch.current_position = position;
+        for (i, text) in pdn.moves.iter().enumerate() {
+            let move_item = scratch.find_move(text).map_err(|err| {
+                let color = if scratch.current_position.next_move == Some(Color::White) { "white" } else { "black" };
+                NotationError(format!("move {} ({}): {}", (i + black_first as usize) / 2 + 1, color, err))
+            })?;
+            scratch.make_move_by_move_item(&move_item);
+        }
+        if !Arc::ptr_eq(&environment, &self.position_environment) {
+            self.position_environment = environment;
+            self.tt.clear();
+            self.ordering = MoveOrdering::new(self.position_environment.pack_to_board.len());
+        }
+        self.current_position = scratch.current_position;
+        // the history is shared, its content is swapped
+        self.position_history.replace(scratch.position_history.replace(PositionHistory::new()));
+        self.tree = None;
+        self.book_learned = false;
+        self.pdn_tags = pdn.tags.iter()
+            .filter(|x| !["FEN", "Result", "GameType"].iter().any(|t| x.0.eq_ignore_ascii_case(t)))
+            .cloned().collect();
+        Ok(())
+    }
+
+    pub fn find_move(&mut self, text: &str) -> Result<MoveItem, NotationError> {
+        let environment = self.current_position.environment.clone();
+        let move_list = self.current_position.get_move_list(true);
+        move_list.find_move(&environment, text).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::notation::Notation;
+    use crate::pdn::PdnGame;
+    use crate::rules::Variant;
+
+    #[test]
+    fn pdn_round_trip() {
+        let mut game = Game::new(8);
+        game.set_fen("W:W21-32:B1-12").unwrap();
+        game.set_depth(2);
+        for _i in 0..12 {
+            if game.position_history.borrow_mut().finish_check().is_some() { break; }
+            let best = game.get_best_move_rust();
+            game.make_move_by_pos_item(&best);
+        }
+        let text = game.to_pdn_game(Notation::Algebraic).to_string();
+        assert!(!text.contains("[FEN"));
+        assert!(text.contains("1. "));
+        let mut loaded = Game::new(8);
+        loaded.load_pdn_game(&PdnGame::parse(&text).unwrap()).unwrap();
+        assert_eq!(loaded.current_position, game.current_position);
+        assert_eq!(loaded.position_history.borrow().len(), game.position_history.borrow().len());
+        assert_eq!(loaded.to_pdn_game(Notation::Algebraic).to_string(), text);
+    }
+
+    #[test]
+    fn pdn_fen_and_numbering() {
+        let text = "[Event \"test\"]\n[FEN \"B:W22,K29:B1,15\"]\n1... 15-18 {forced} 2. 22x15 *";
+        let pdn = PdnGame::parse(text).unwrap();
+        assert_eq!(pdn.moves, vec!["15-18", "22x15"]);
+        let mut game = Game::new(8);
+        game.load_pdn_game(&pdn).unwrap();
+        assert_eq!(game.get_fen(), "B:W15,K29:B1");
+        let out = game.to_pdn_game(Notation::Numeric);
+        assert_eq!(out.tag("Event"), Some("test"));
+        assert_eq!(out.tag("FEN"), Some("B:W22,K29:B1,15"));
+        assert!(out.to_string().contains("1... 15-18 2. 22:15 *"));
+        assert_eq!(out.result, Some("*".to_string()));
+    }
+
+    #[test]
+    fn pdn_illegal_move() {
+        let pdn = PdnGame::parse("1. c3-d4 f6-g5 2. d4-e3 *").unwrap();
+        let mut game = Game::new(8);
+        let err = game.load_pdn_game(&pdn).unwrap_err();
+        assert!(err.0.contains("move 2 (white)"), "{}", err);
+        assert!(err.0.contains("illegal move 'd4-e3'"), "{}", err);
+        // the game is left as it was
+        let mut game = Game::new(8);
+        game.set_start_position();
+        let move_item = game.find_move("22-18").unwrap();
+        game.make_move_by_move_item(&move_item);
+        let (fen, history) = (game.get_fen(), game.position_history.borrow().len());
+        let pdn = PdnGame::parse("[GameType \"20\"]\n1. 32-28 19-23 2. 28-32 *").unwrap();
+        assert!(game.load_pdn_game(&pdn).is_err());
+        assert_eq!(game.current_position.environment.size, 8);
+        assert_eq!(game.get_fen(), fen);
+        assert_eq!(game.position_history.borrow().len(), history);
+    }
+
+    #[test]
+    fn pdn_many_games() {
+        let text = "[Event \"a\"]\n[Result \"2-0\"]\n\n1. 22-18 11-15\n2. 18x11 2-0\n\n[Event \"b\"]\n1. 23-19 *\n";
+        let games = PdnGame::parse_all(text).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].moves, vec!["22-18", "11-15", "18x11"]);
+        assert_eq!(games[0].result.as_deref(), Some("2-0"));
+        assert_eq!(games[1].tag("Event"), Some("b"));
+        assert_eq!(PdnGame::parse_all("1. 22-18 *").unwrap().len(), 1);
+        assert!(PdnGame::parse_all("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn pdn_game_type() {
+        let pdn = PdnGame::parse("[GameType \"20,W,10,10,N2,0\"]\n1. 32-28 19-23 *").unwrap();
+        let mut game = Game::new(8);
+        game.load_pdn_game(&pdn).unwrap();
+        assert_eq!(game.variant(), Variant::International);
+        assert_eq!(game.current_position.environment.size, 10);
+        assert_eq!(game.to_pdn_game(Notation::Numeric).tag("GameType"), Some("20"));
+        // Russian draughts on a 10x10 board
+        let mut game = Game::new(10);
+        game.set_start_position();
+        let move_item = game.find_move("31-27").unwrap();
+        game.make_move_by_move_item(&move_item);
+        let pdn = game.to_pdn_game(Notation::Numeric);
+        assert_eq!(pdn.tag("GameType"), Some("25,W,10,10,N2,0"));
+        let mut loaded = Game::new(8);
+        loaded.load_pdn_game(&PdnGame::parse(&pdn.to_string()).unwrap()).unwrap();
+        assert_eq!(loaded.variant(), Variant::Russian);
+        assert_eq!(loaded.get_fen(), game.get_fen());
+        assert!(loaded.load_pdn_game(&PdnGame::parse("[GameType \"25,W,9,9,N2,0\"]\n*").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::bitboard::BitPosition;
+use crate::moves_list::MoveItem;
+use crate::position::Position;
+
+impl Position {
+    // number of leaf nodes at `depth` plies, engine move generation (for_front = false)
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        let environment = self.environment.clone();
+        if let Some(ref geometry) = environment.bit_geometry {
+            return BitPosition::from_position(geometry, self).perft(geometry, depth);
+        }
+        self.perft_by_cells(depth)
+    }
+
+    pub fn perft_by_cells(&mut self, depth: u32) -> u64 {
+        if depth == 0 { return 1; }
+        let move_list = self.get_move_list_by_cells(false);
+        if depth == 1 { return move_list.list.len() as u64; }
+        let mut nodes = 0;
+        for move_item in &move_list.list {
+            self.make_move(move_item);
+            let took_pieces = self.took_pieces.clone();
+            nodes += self.perft_by_cells(depth - 1);
+            self.took_pieces = took_pieces;
+            self.unmake_move(move_item);
+        }
+        nodes
+    }
+
+    // perft subtotals per root move
+    pub fn divide(&mut self, depth: u32) -> Vec<(MoveItem, u64)> {
+        if depth == 0 { return vec![]; }
+        let move_list = self.get_move_list(false);
+        let mut res = Vec::with_capacity(move_list.list.len());
+        for move_item in move_list.list {
+            self.make_move(&move_item);
+            let took_pieces = self.took_pieces.clone();
+            let nodes = self.perft(depth - 1);
+            self.took_pieces = took_pieces;
+            self.unmake_move(&move_item);
+            res.push((move_item, nodes));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::Color;
+    use crate::game::Game;
+    use crate::piece::Piece;
+    use crate::position::Position;
+
+    fn setup(white: &[(usize, bool)], black: &[(usize, bool)], next_move: Color) -> Position {
+        let mut game = Game::new(8);
+        for (pos, is_king) in white {
+            game.insert_piece(Piece::new(game.to_pack(*pos), Color::White, *is_king));
+        }
+        for (pos, is_king) in black {
+            game.insert_piece(Piece::new(game.to_pack(*pos), Color::Black, *is_king));
+        }
+        game.current_position.next_move = Some(next_move);
+        game.current_position
+    }
+
+    fn start() -> Position {
+        let game = Game::new(8);
+        Position::start_position(game.current_position.environment.clone())
+    }
+
+    #[test]
+    fn perft_start_position() {
+        let mut pos = start();
+        let expected: [u64; 9] = [1, 7, 49, 302, 1469, 7482, 37986, 190146, 929905];
+        for (depth, nodes) in expected.iter().enumerate() {
+            assert_eq!(pos.perft(depth as u32), *nodes, "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn perft_generators_agree() {
+        let mut pos = start();
+        for depth in 0..6 {
+            assert_eq!(pos.perft(depth), pos.perft_by_cells(depth), "depth {}", depth);
+        }
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let mut pos = start();
+        let divide = pos.divide(5);
+        assert_eq!(divide.len(), 7);
+        assert_eq!(divide.iter().map(|x| x.1).sum::<u64>(), pos.perft(5));
+    }
+
+    #[test]
+    fn perft_king_captures() {
+        // (white, black, side to move, [(depth, nodes)])
+        let table: Vec<(Vec<(usize, bool)>, Vec<(usize, bool)>, Color, Vec<(u32, u64)>)> = vec![
+            // king on the main road in front of a chequerboard of men
+            (vec![(0, true)], vec![(9, false), (11, false), (13, false), (25, false), (27, false),
+                                   (29, false), (41, false), (43, false), (45, false)],
+             Color::White, vec![(1, 42), (2, 128), (3, 720), (4, 3543), (5, 20452)]),
+            // strike chains with and without promotion (game_strike_list)
+            (vec![(47, false), (63, false), (15, true)], vec![(54, false), (43, false), (20, false)],
+             Color::White, vec![(1, 5), (2, 10), (3, 44), (4, 124), (5, 919), (6, 3738)]),
+            // one king against three
+            (vec![(31, true)], vec![(43, true), (36, true), (20, true)],
+             Color::Black, vec![(1, 28), (2, 174), (3, 1805), (4, 10560), (5, 127054)]),
+            // two kings against four men
+            (vec![(0, true), (9, true)], vec![(52, false), (54, false), (38, false), (63, false)],
+             Color::White, vec![(1, 6), (2, 31), (3, 210), (4, 830), (5, 5191), (6, 20334)]),
+        ];
+        for (white, black, color, expected) in table {
+            let mut pos = setup(&white, &black, color);
+            for (depth, nodes) in expected {
+                assert_eq!(pos.perft(depth), nodes, "depth {}", depth);
+                assert_eq!(pos.perft_by_cells(depth), nodes, "depth {}", depth);
+            }
+        }
+    }
+}
@@ -1,8 +1,7 @@
-use std::cmp::Ordering;
 use std::io;
 use std::io::Write;
 use std::mem::swap;
-use std::rc::Rc;
+use std::sync::Arc;
 use rand::{Rng, thread_rng};
 
 use serde::{Deserialize, Serialize};
@@ -17,6 +16,9 @@ use wasm_bindgen::prelude::wasm_bindgen;
 use crate::game::BestPos;
 use crate::PositionHistory::PositionAndMove;
 use crate::random;
+use crate::bitboard::BitPosition;
+use crate::zobrist;
+use crate::eval::MAX_EVAL;
 
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
@@ -49,18 +51,6 @@ impl PartialEq for PosState {
 
 impl Eq for PosState {}
 
-impl PartialOrd<Self> for PosState {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Ord for PosState {
-    fn cmp(&self, other: &Self) -> Ordering {
-        Ord::cmp(&self.evaluate(), &other.evaluate())
-    }
-}
-
 impl PosState {
     pub fn get_count(&mut self, color: Color) -> &mut PieceCount {
         if color == Color::Black { &mut self.black } else { &mut self.white }
@@ -72,11 +62,6 @@ impl PosState {
         let cnt = self.get_count(color);
         (cnt.king + cnt.simple) as i32
     }
-
-    pub fn evaluate(&self) -> i32 {
-        self.white.simple as i32 * 1000 + self.white.king as i32 * 3000
-            - self.black.simple as i32 * 1000 - self.black.king as i32 * 3000
-    }
 }
 
 
@@ -87,13 +72,19 @@ pub struct Position {
     pub cells: Vec<Option<Piece>>,
     pub state: PosState,
     pub next_move: Option<Color>,
-    move_list: Rc<Option<MoveList>>,
+    move_list: Arc<Option<MoveList>>,
     #[serde(skip_serializing)]
     pub eval: Option<i32>,
     #[serde(skip_serializing)]
-    pub environment: Rc<PositionEnvironment>,
+    pub environment: Arc<PositionEnvironment>,
     #[serde(skip_serializing)]
     pub took_pieces: Vec<Option<Piece>>,
+    // Zobrist key of the pieces, the side to move is added by zobrist()
+    #[serde(skip_serializing)]
+    hash: u64,
+    // the pieces as bitboards, kept with the cells when the size has a BitGeometry
+    #[serde(skip_serializing)]
+    bits: Option<BitPosition>,
 }
 
 impl PartialEq for Position {
@@ -104,7 +95,8 @@ impl PartialEq for Position {
 }
 
 impl Position {
-    pub fn new(environment: Rc<PositionEnvironment>) -> Position {
+    pub fn new(environment: Arc<PositionEnvironment>) -> Position {
+        let bits = environment.bit_geometry.as_ref().map(|_| BitPosition::new(Color::White));
         let mut pos = Position {
             state: PosState {
                 black: { PieceCount { king: 0, simple: 0 } },
@@ -119,9 +111,11 @@ impl Position {
             cells: Vec::new(),
             environment,
             next_move: None,
-            move_list: Rc::new(None),
+            move_list: Arc::new(None),
             eval: None,
             took_pieces: vec![],
+            hash: 0,
+            bits,
         };
         pos.cells = Vec::new();
         let size = pos.environment.size;
@@ -129,6 +123,19 @@ impl Position {
         pos
     }
 
+    // men on all dark squares of the first size / 2 - 1 rows of each side
+    pub fn start_position(environment: Arc<PositionEnvironment>) -> Position {
+        let mut pos = Position::new(environment);
+        let len = pos.cells.len();
+        let rows = pos.environment.size as usize / 2 - 1;
+        for i in 0..rows * pos.environment.size as usize / 2 {
+            pos.insert_piece(Piece::new(i, Color::White, false));
+            pos.insert_piece(Piece::new(len - 1 - i, Color::Black, false));
+        }
+        pos.next_move = Some(pos.environment.rules.first_move);
+        pos
+    }
+
     pub fn print_pos(&self) {
         let pieces: Vec<_> = self.cells.iter().filter(|x| x.is_some()).collect();
         let pieces: Vec<_> = pieces.iter().map(|x| {
@@ -140,10 +147,10 @@ impl Position {
         io::stdout().flush().unwrap();
     }
 
-    pub fn get_move_list_cached(&mut self) -> Rc<Option<MoveList>> {
+    pub fn get_move_list_cached(&mut self) -> Arc<Option<MoveList>> {
         if self.move_list.is_none() {
             let move_li = self.get_move_list(false);
-            self.move_list = Rc::new(Option::from(move_li));
+            self.move_list = Arc::new(Option::from(move_li));
         }
         self.move_list.clone()
     }
@@ -161,19 +168,41 @@ impl Position {
         self.state.get_count(color).simple = (self.state.get_count(color).simple as i32 - sign) as u32;
     }
 
+    // 64-bit Zobrist key of the pieces and the side to move, kept up to date incrementally
+    pub fn zobrist(&self) -> u64 {
+        self.hash ^ zobrist::side_key(self.next_move)
+    }
+
+    // key computed from scratch, for checks
+    pub fn zobrist_by_cells(&self) -> u64 {
+        self.cells.iter().flatten().fold(zobrist::side_key(self.next_move), |acc, x| acc ^ x.zobrist())
+    }
+
+    fn hash_cell(&mut self, pos: BoardPos) {
+        if let Some(ref piece) = self.cells[pos] {
+            self.hash ^= zobrist::piece_key(pos, piece.color, piece.is_king);
+            if let (Some(geometry), Some(bits)) = (&self.environment.bit_geometry, self.bits.as_mut()) {
+                bits.toggle(geometry, pos, piece.color, piece.is_king);
+            }
+        }
+    }
+
     pub fn insert_piece(&mut self, piece: Piece) {
         let pos = piece.pos as usize;
         self.state_change(&piece, 1);
+        self.hash_cell(pos);
         self.cells[pos] = Some(piece);
-        self.move_list = Rc::new(None);
+        self.hash_cell(pos);
+        self.move_list = Arc::new(None);
         self.eval = None;
     }
 
     pub fn remove_piece(&mut self, pos: BoardPos) -> bool {
         if let Some(piece) = self.cells[pos].clone() {
             self.state_change(&piece, -1);
+            self.hash_cell(pos);
             self.cells[pos] = None;
-            self.move_list = Rc::new(None);
+            self.move_list = Arc::new(None);
             self.eval = None;
             return true;
         }
@@ -189,11 +218,13 @@ impl Position {
             }
         }
         if mov.is_king() {
+            self.hash_cell(mov.to());
             let color = {
                 let piece = self.cells[mov.to()].as_mut().unwrap();
                 piece.is_king = true;
                 piece.color
             };
+            self.hash_cell(mov.to());
             self.state_change_by_king_color(color, 1);
         }
     }
@@ -206,19 +237,23 @@ impl Position {
             }
         }
         if mov.is_king() {
+            self.hash_cell(mov.from());
             let color = {
                 let piece = self.cells[mov.from()].as_mut().unwrap();
                 piece.is_king = false;
                 piece.color
             };
+            self.hash_cell(mov.from());
             self.state_change_by_king_color(color, -1);
         }
     }
 
-    fn get_piece_by_v(&self, v: &Rc<Vec<BoardPos>>, i: usize) -> &Option<Piece> {
+    fn get_piece_by_v(&self, v: &Arc<Vec<BoardPos>>, i: usize) -> &Option<Piece> {
         &self.cells[v[i]]
     }
     pub fn swap(&mut self, i: BoardPos, j: BoardPos) {
+        self.hash_cell(i);
+        self.hash_cell(j);
         self.cells.swap(i as usize, j as usize);
         let set_pos = |cell: &mut Option<Piece>, pos: BoardPos| {
             if let Some(ref mut piece) = cell {
@@ -227,14 +262,16 @@ impl Position {
         };
         set_pos(&mut self.cells[i], i);
         set_pos(&mut self.cells[j], j);
+        self.hash_cell(i);
+        self.hash_cell(j);
     }
 
-    fn straight_strike(&mut self, v: &Rc<Vec<BoardPos>>) -> Option<StraightStrike> {
+    fn straight_strike(&mut self, v: &Arc<Vec<BoardPos>>) -> Option<StraightStrike> {
         if v.len() < 3 {
             return None;
         }
         if let Some(piece) = self.get_piece_by_v(v, 0) {
-            let search_steps_top = if piece.is_king { v.len() } else { 3 };
+            let search_steps_top = if piece.is_king && self.environment.rules.flying_kings { v.len() } else { 3 };
             let mut i: usize = 2;
             while i < search_steps_top {
                 if let Some(candidate) = self.get_piece_by_v(v, i - 1) {
@@ -253,7 +290,7 @@ impl Position {
                             from: v[0],
                             to: v[i],
                             take: v[i - 1],
-                            king_move: self.environment.is_king_move_for(piece, v[i]),
+                            king_move: self.environment.is_king_move_for(piece, v[i], false),
                         };
                         return Some(strike);
                     } else { break; }
@@ -264,9 +301,11 @@ impl Position {
         None
     }
 
-    fn get_vectors(&self, piece: &Piece, ban_directions: &Vec<i8>, for_strike: bool) -> Vec<Rc<Vector<BoardPos>>> {
+    fn get_vectors(&self, piece: &Piece, ban_directions: &Vec<i8>, for_strike: bool) -> Vec<Arc<Vector<BoardPos>>> {
         let d2_4 = {
-            if piece.is_king || for_strike { vec![0, 1, 2, 3] } else if piece.color == Color::White {
+            if piece.is_king || (for_strike && self.environment.rules.men_capture_backward) {
+                vec![0, 1, 2, 3]
+            } else if piece.color == Color::White {
                 vec![0, 1]
             } else {
                 vec![2, 3]
@@ -297,7 +336,9 @@ impl Position {
             let vectors: Vec<_> = self.get_vectors(piece, &vec![], false);
             for vector in vectors {
                 for point in {
-                    if piece.is_king { &(vector.points)[1..] } else { &(vector.points)[1..2] }
+                    if piece.is_king && self.environment.rules.flying_kings {
+                        &(vector.points)[1..]
+                    } else { &(vector.points)[1..2] }
                 } {
                     if self.cells[*point].is_some() { break; }
                     move_list.list.push(
@@ -305,7 +346,7 @@ impl Position {
                             mov: Some(QuietMove {
                                 from: pos,
                                 to: *point,
-                                king_move: self.environment.is_king_move_for(piece, *point),
+                                king_move: self.environment.is_king_move_for(piece, *point, false),
                             }),
                             strike: None,
                         })
@@ -319,25 +360,30 @@ impl Position {
     pub fn evaluate(&mut self) -> i32 {
         if self.eval.is_some() { return self.eval.unwrap(); }
         // white advantage if positive signature of evaluate, black - negative
-        let mut eval: i32 =
-            if self.get_move_list_cached().as_ref().as_ref().unwrap().list.len() == 0 {
-                if self.next_move.is_some() && self.next_move.unwrap() == Color::White {
-                    i32::MIN / 2
-                } else { i32::MAX / 2 }
-            } else { 0 };
-
-        for cell in &self.cells {
+        let white_to_move = self.next_move == Some(Color::White);
+        let no_moves = self.get_move_list_cached().as_ref().as_ref().unwrap().list.len() == 0;
+        let params = &self.environment.eval;
+        // summed wide, user weights can be anything
+        let mut eval: i64 =
+            if no_moves { 0 } else if white_to_move { params.tempo as i64 } else { -params.tempo as i64 };
+
+        for (pos, cell) in self.cells.iter().enumerate() {
             if let Some(ref piece) = cell {
-                let v = self.get_vectors(piece, &vec![], false);
-                let s: i32 = if piece.color == Color::White { 1 } else { -1 };
-                v.iter().for_each(|v|
-                    for point in &(v.points)[1..] {
-                        if self.cells[*point].is_some() { break; }
-                        eval += s;
-                    })
+                let s: i64 = if piece.color == Color::White { 1 } else { -1 };
+                if params.mobility != 0 {
+                    let v = self.get_vectors(piece, &vec![], false);
+                    v.iter().for_each(|v|
+                        for point in &(v.points)[1..] {
+                            if self.cells[*point].is_some() { break; }
+                            eval += s * params.mobility as i64;
+                        })
+                }
+                eval += s * params.piece(piece, pos, &self.environment);
             }
         }
-        eval += self.state.evaluate();
+        eval += params.material(&self.state.white, &self.state.black);
+        let mut eval = eval.clamp(-MAX_EVAL, MAX_EVAL) as i32;
+        if no_moves { eval += if white_to_move { i32::MIN / 2 } else { i32::MAX / 2 }; }
         self.eval = Some(eval);
         eval
     }
@@ -350,8 +396,8 @@ impl Position {
         for_front: bool,
     ) -> bool {
         let mut success_call = false;
-        if let Some(piece) = &self.cells[pos] {
-            let vectors: Vec<_> = self.get_vectors(piece, ban_directions, true);
+        if let Some(piece) = self.cells[pos].clone() {
+            let vectors: Vec<_> = self.get_vectors(&piece, ban_directions, true);
             for v in vectors {
                 let points = &v.points;
                 let strike = self.straight_strike(points);
@@ -362,6 +408,7 @@ impl Position {
                     let mut strike_move = straight_strike.clone();
                     for pos in &straight_strike {
                         strike_move.to = pos;
+                        strike_move.king_move = self.environment.is_king_move_for(&piece, pos, true);
                         self.make_strike_or_move(&mut strike_move);
                         move_list.current_chain.vec.push(strike_move.clone());
                         if strike_move.king_move { move_list.current_chain.king_move = true; }
@@ -397,6 +444,7 @@ impl Position {
         } else if let Some(ref strike) = move_item.strike {
             self.took_pieces = vec![None; strike.vec.len()];
             for (i, straight_strike) in strike.vec.iter().enumerate() {
+                self.hash_cell(straight_strike.take);
                 swap(&mut self.took_pieces[i], &mut self.cells[straight_strike.take]);
                 self.state_change(self.took_pieces[i].clone().as_ref().unwrap(), -1);
             };
@@ -409,7 +457,7 @@ impl Position {
             self.make_strike_or_move(mov);
         }
         if self.next_move.is_some() { self.next_move = Some(!self.next_move.unwrap()) }
-        self.move_list = Rc::new(None);
+        self.move_list = Arc::new(None);
         self.eval = None;
     }
 
@@ -421,6 +469,7 @@ impl Position {
             for (i, straight_strike) in strike.vec.iter().enumerate() {
                 self.state_change(self.took_pieces[i].clone().as_ref().unwrap(), 1);
                 swap(&mut self.took_pieces[i], &mut self.cells[straight_strike.take]);
+                self.hash_cell(straight_strike.take);
             };
             let ref mut mov = QuietMove {
                 from: strike.vec[0].from,
@@ -430,6 +479,9 @@ impl Position {
             self.unmake_strike_or_move(mov);
         }
         if self.next_move.is_some() { self.next_move = Some(!self.next_move.unwrap()) }
+        // caches were filled for the position after the move
+        self.move_list = Arc::new(None);
+        self.eval = None;
     }
 
     pub fn make_move_and_get_position(&mut self, move_item: &MoveItem) -> PositionAndMove {
@@ -438,6 +490,16 @@ impl Position {
     }
 
     pub fn get_move_list(&mut self, for_front: bool) -> MoveList {
+        let environment = self.environment.clone();
+        if let (Some(geometry), Some(bits)) = (&environment.bit_geometry, self.bits.as_mut()) {
+            bits.next_move = self.next_move.unwrap_or_else(|| panic!("Color of next move undefined!"));
+            return bits.get_move_list(geometry, for_front);
+        }
+        self.get_move_list_by_cells(for_front)
+    }
+
+    // reference generator walking cells and vectors, kept for cross-checks with the bitboard one
+    pub fn get_move_list_by_cells(&mut self, for_front: bool) -> MoveList {
         let color = self.next_move.unwrap_or_else(|| panic!("Color of next move undefined!"));
         let pieces_pos: Vec<_> = self.cells.iter()
             .filter(|piece| if let Some(piece) = piece { piece.color == color } else { false })
@@ -452,6 +514,8 @@ impl Position {
             for pos in pieces_pos {
                 self.get_quiet_move_list(pos, &mut move_list);
             }
+        } else if self.environment.rules.majority_capture {
+            move_list.retain_longest_strikes();
         }
         move_list
     }
@@ -4,7 +4,7 @@ use crate::moves::BoardPos;
 use crate::color::Color;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
-use std::rc::Rc;
+use std::sync::Arc;
 use wasm_bindgen::prelude::wasm_bindgen;
 use wasm_bindgen::JsValue;
 use crate::moves_list::MoveList;
@@ -12,6 +12,9 @@ use crate::piece::Piece;
 use ts_rs::TS;
 use crate::game::Game;
 use crate::vector::Vector;
+use crate::bitboard::BitGeometry;
+use crate::rules::RuleSet;
+use crate::eval::EvalParams;
 
 #[derive(Clone, Deserialize, Serialize, Debug, TS)]
 #[ts(export)]
@@ -22,11 +25,11 @@ pub struct Grade {
 }
 
 impl Grade {
-    pub fn get(&mut self, piece: &Piece) -> &mut i16 {
-        if piece.is_king { return &mut self.is_king; }
+    pub fn get(&self, piece: &Piece) -> i16 {
+        if piece.is_king { return self.is_king; }
         return match piece.color {
-            Color::Black => { &mut self.black }
-            Color::White => { &mut self.white }
+            Color::Black => { self.black }
+            Color::White => { self.white }
         };
     }
 }
@@ -38,84 +41,23 @@ pub struct PositionEnvironment {
     pub size: i8,
     king_row_black: usize,
     king_row_white: usize,
-    vectors_map: Vec<Vec<Rc<Vector<BoardPos>>>>,
+    vectors_map: Vec<Vec<Arc<Vector<BoardPos>>>>,
     pub(crate) board_to_pack: Vec<BoardPos>,
     pub(crate) pack_to_board: Vec<BoardPos>,
     pub(crate) cell_grade: Vec<Grade>,
+    #[wasm_bindgen(skip)]
+    pub rules: RuleSet,
+    #[wasm_bindgen(skip)]
+    pub eval: EvalParams,
+    #[serde(skip)]
+    pub(crate) bit_geometry: Option<BitGeometry>,
 }
 
 #[wasm_bindgen]
 impl PositionEnvironment {
     #[wasm_bindgen(constructor)]
     pub fn new(size: i8) -> Self {
-        if size % 2 != 0 {
-            panic!("Size must be even")
-        }
-        let size2 = (size * size) as BoardPos;
-        let is_black_cell = |i: BoardPos| -> bool { (i / size as BoardPos + i % 2) % 2 == 0 };
-        let is_on_board = |i: BoardPos| -> bool { i < size2 && is_black_cell(i) };
-        let d4 = vec![size + 1, size - 1, -(size + 1), -(size - 1)];
-        let mut vectors_map = Vec::new();
-        let mut board_to_pack: Vec<BoardPos> = Vec::new();
-        board_to_pack.resize(size2 as usize, 0);
-        let mut pack_to_board: Vec<BoardPos> = Vec::with_capacity((size2 / 2) as usize);
-        pack_to_board.resize((size2 / 2) as usize, 0);
-        // packing board is array with only black cells
-        let mut j: BoardPos = 0;
-        for i in 0..size2 as BoardPos {
-            if is_black_cell(i) {
-                board_to_pack[i] = j;
-                pack_to_board[j] = i;
-                j += 1;
-            }
-        }
-        // vectors_map for packing board
-        for i in 0..size2 {
-            if is_black_cell(i) {
-                let mut direction_index: i8 = 0;
-                let mut d4_v_list = Vec::new();
-                for d in d4.iter() {
-                    let mut p = i;
-
-                    let mut points = vec![board_to_pack[p]];
-                    loop {
-                        p = ((p as i64) + (*d as i64)) as BoardPos;
-                        if !is_on_board(p) {
-                            break;
-                        }
-                        points.push(board_to_pack[p as usize]);
-                    }
-                    let v: Vector<BoardPos> =
-                        Vector::new(direction_index, points);
-
-                    if v.points.len() > 1 {
-                        d4_v_list.push(Rc::new(v));
-                    }
-                    direction_index += 1;
-                }
-                vectors_map.push(d4_v_list);
-            }
-        }
-        let mut cell_grade: Vec<Grade> = Vec::new();
-        for v in &vectors_map {
-            let mut b = 0;
-            let mut k: i16 = 0;
-            let mut w = 0;
-            for v_d in v {
-                if v_d.direction < 2 { w += 1; } else { b += 1; }
-                k += v_d.points.len() as i16;
-            }
-            cell_grade.push(Grade { white: w, is_king: k, black: b })
-        }
-        PositionEnvironment {
-            pack_to_board,
-            board_to_pack,
-            cell_grade,
-            vectors_map,
-            size,
-            king_row_black: size as usize / 2,
-            king_row_white: (size2 - size as usize) / 2 - 1,
-        }
+        PositionEnvironment::with_rules(RuleSet::russian(size))
     }
 
 
@@ -126,8 +68,9 @@ impl PositionEnvironment {
         JsValue::from_str(&s)
     }
 
-    pub fn is_king_move_for(&self, piece: &Piece, pos: BoardPos) -> bool {
-        if piece.is_king { return false; }
+    // `mid_capture`: the piece goes on striking from `pos`
+    pub fn is_king_move_for(&self, piece: &Piece, pos: BoardPos, mid_capture: bool) -> bool {
+        if piece.is_king || (mid_capture && !self.rules.promote_mid_capture) { return false; }
         if piece.color == Color::White {
             pos > self.king_row_white
         } else {
@@ -175,7 +118,7 @@ impl PositionEnvironment {
     #[wasm_bindgen]
     pub fn test() -> JsValue {
         let game = PositionEnvironment::new(8);
-        let mut pos = Position::new(Rc::new(game));
+        let mut pos = Position::new(Arc::new(game));
         pos.insert_piece(Piece::new(22, Color::White, false));
         pos.insert_piece(Piece::new(4, Color::Black, true));
         pos.insert_piece(Piece::new(21, Color::Black, true));
@@ -232,7 +175,82 @@ impl PositionEnvironment {
 }
 
 impl PositionEnvironment {
-    pub fn get_vectors(&self, pos: usize) -> &Vec<Rc<Vector<BoardPos>>> {
+    pub fn with_rules(rules: RuleSet) -> Self {
+        let size = rules.size;
+        if size % 2 != 0 {
+            panic!("Size must be even")
+        }
+        let size2 = (size * size) as BoardPos;
+        let is_black_cell = |i: BoardPos| -> bool { (i / size as BoardPos + i % 2) % 2 == 0 };
+        let is_on_board = |i: BoardPos| -> bool { i < size2 && is_black_cell(i) };
+        let d4 = vec![size + 1, size - 1, -(size + 1), -(size - 1)];
+        let mut vectors_map = Vec::new();
+        let mut board_to_pack: Vec<BoardPos> = Vec::new();
+        board_to_pack.resize(size2 as usize, 0);
+        let mut pack_to_board: Vec<BoardPos> = Vec::with_capacity((size2 / 2) as usize);
+        pack_to_board.resize((size2 / 2) as usize, 0);
+        // packing board is array with only black cells
+        let mut j: BoardPos = 0;
+        for i in 0..size2 as BoardPos {
+            if is_black_cell(i) {
+                board_to_pack[i] = j;
+                pack_to_board[j] = i;
+                j += 1;
+            }
+        }
+        // vectors_map for packing board
+        for i in 0..size2 {
+            if is_black_cell(i) {
+                let mut direction_index: i8 = 0;
+                let mut d4_v_list = Vec::new();
+                for d in d4.iter() {
+                    let mut p = i;
+
+                    let mut points = vec![board_to_pack[p]];
+                    loop {
+                        p = ((p as i64) + (*d as i64)) as BoardPos;
+                        if !is_on_board(p) {
+                            break;
+                        }
+                        points.push(board_to_pack[p as usize]);
+                    }
+                    let v: Vector<BoardPos> =
+                        Vector::new(direction_index, points);
+
+                    if v.points.len() > 1 {
+                        d4_v_list.push(Arc::new(v));
+                    }
+                    direction_index += 1;
+                }
+                vectors_map.push(d4_v_list);
+            }
+        }
+        let mut cell_grade: Vec<Grade> = Vec::new();
+        for v in &vectors_map {
+            let mut b = 0;
+            let mut k: i16 = 0;
+            let mut w = 0;
+            for v_d in v {
+                if v_d.direction < 2 { w += 1; } else { b += 1; }
+                k += v_d.points.len() as i16;
+            }
+            cell_grade.push(Grade { white: w, is_king: k, black: b })
+        }
+        PositionEnvironment {
+            pack_to_board,
+            board_to_pack,
+            cell_grade,
+            vectors_map,
+            bit_geometry: BitGeometry::new(&rules),
+            rules,
+            eval: EvalParams::default(),
+            size,
+            king_row_black: size as usize / 2,
+            king_row_white: (size2 - size as usize) / 2 - 1,
+        }
+    }
+
+    pub fn get_vectors(&self, pos: usize) -> &Vec<Arc<Vector<BoardPos>>> {
         &self.vectors_map[pos]
     }
 }
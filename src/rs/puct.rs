@@ -0,0 +1,390 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use crate::color::Color;
+use crate::game::{BestPos, Game};
+use crate::mcts::{eval_value, finish_value, McTree, Node};
+use crate::moves_list::MoveItem;
+use crate::position::Position;
+use crate::PositionHistory::PositionAndMove;
+
+// taken from the value of the nodes of a line waiting for its evaluation, so that the other
+// lines of the batch go elsewhere
+const VIRTUAL_LOSS: f64 = 1.0;
+
+// position to evaluate and its moves, in the order of the policy
+#[derive(Clone, Debug)]
+pub struct EvalRequest {
+    pub pos: Position,
+    pub moves: Vec<MoveItem>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    // prior of each move of the request
+    pub policy: Vec<f32>,
+    // expected result for the side to move, from -1 to 1
+    pub value: f32,
+}
+
+// Policy and value of positions for PUCT, a network or anything else. Requests come in
+// batches of up to PuctConfig::batch_size.
+pub trait Evaluator {
+    // an evaluation per request, in their order
+    fn evaluate(&mut self, requests: &[EvalRequest]) -> Vec<Evaluation>;
+}
+
+// uniform policy and the static evaluation as value
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StaticEvaluator;
+
+impl Evaluator for StaticEvaluator {
+    fn evaluate(&mut self, requests: &[EvalRequest]) -> Vec<Evaluation> {
+        requests.iter().map(|request| {
+            let mut pos = request.pos.clone();
+            let eval = pos.evaluate();
+            let eval = if pos.next_move == Some(Color::White) { eval } else { -eval };
+            Evaluation {
+                policy: vec![1.0 / request.moves.len().max(1) as f32; request.moves.len()],
+                value: eval_value(eval) as f32,
+            }
+        }).collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+#[serde(default)]
+pub struct PuctConfig {
+    // weight of the prior term
+    pub c_puct: f64,
+    // leaves evaluated together
+    pub batch_size: usize,
+    // Dirichlet noise mixed into the root priors, for self-play
+    pub root_noise: bool,
+    pub dirichlet_alpha: f64,
+    pub noise_fraction: f64,
+    // value of a move not visited yet
+    pub first_play_urgency: f64,
+    pub passes: i32,
+}
+
+impl Default for PuctConfig {
+    fn default() -> Self {
+        PuctConfig {
+            c_puct: 1.5,
+            batch_size: 8,
+            root_noise: false,
+            dirichlet_alpha: 0.3,
+            noise_fraction: 0.25,
+            first_play_urgency: 0.0,
+            passes: 800,
+        }
+    }
+}
+
+// Marsaglia and Tsang, shape below 1 boosted by U^(1 / shape)
+fn gamma(shape: f64, rng: &mut impl Rng) -> f64 {
+    if shape < 1.0 {
+        return gamma(shape + 1.0, rng) * rng.gen::<f64>().powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        // Box-Muller
+        let normal = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt() * (std::f64::consts::TAU * rng.gen::<f64>()).cos();
+        let v = (1.0 + c * normal).powi(3);
+        if v <= 0.0 { continue; }
+        let u = 1.0 - rng.gen::<f64>();
+        if u.ln() < 0.5 * normal * normal + d - d * v + d * v.ln() { return d * v; }
+    }
+}
+
+// sample of the symmetric Dirichlet distribution of `n` values
+pub fn dirichlet(alpha: f64, n: usize, rng: &mut impl Rng) -> Vec<f64> {
+    let sample: Vec<f64> = (0..n).map(|_| gamma(alpha, rng)).collect();
+    let sum: f64 = sample.iter().sum();
+    if sum > 0.0 { sample.iter().map(|x| x / sum).collect() } else { vec![1.0 / n as f64; n] }
+}
+
+// end of a line of the batch
+struct Leaf {
+    path: Vec<Rc<RefCell<Node>>>,
+    // value of a finished game for the side that made the last move
+    value: Option<f64>,
+    // index of the evaluation request
+    request: Option<usize>,
+}
+
+impl McTree {
+    fn expand_with(&mut self, node: &Rc<RefCell<Node>>, moves: Vec<MoveItem>, policy: &[f32]) {
+        let mut node = node.borrow_mut();
+        if !node.childs.is_empty() { return; }
+        let sum: f32 = policy.iter().take(moves.len()).sum();
+        let n = moves.len();
+        for (i, mov) in moves.into_iter().enumerate() {
+            let mut child = Node::new(Some(mov));
+            child.P = match policy.get(i) {
+                Some(p) if sum > 0.0 => p / sum,
+                _ => 1.0 / n as f32
+            };
+            node.childs.push(Rc::new(RefCell::new(child)));
+        }
+        self.nodes += n;
+    }
+
+    fn select(node: &Node, config: &PuctConfig) -> Rc<RefCell<Node>> {
+        let sqrt_n = (node.N.max(1) as f64).sqrt();
+        let uniform = node.childs.iter().all(|x| x.borrow().P == 0.0);
+        let score = |child: &Node| {
+            let q = if child.N > 0 { child.W / child.N as f64 } else { config.first_play_urgency };
+            let p = if uniform { 1.0 / node.childs.len() as f64 } else { child.P as f64 };
+            q + config.c_puct * p * sqrt_n / (1.0 + child.N as f64)
+        };
+        node.childs.iter().max_by(|a, b| score(&a.borrow()).total_cmp(&score(&b.borrow()))).unwrap().clone()
+    }
+
+    // a line down to an unexpanded node or a finished game, with virtual losses on its nodes
+    fn select_leaf(&mut self, config: &PuctConfig, requests: &mut Vec<EvalRequest>) -> Leaf {
+        let hist_len = self.history.borrow().len();
+        let mut node = self.root.clone();
+        let mut pos = self.pos.clone();
+        let mut path = vec![];
+        node.borrow_mut().N += 1;
+        let mut leaf = Leaf { path: vec![], value: None, request: None };
+        while !node.borrow().childs.is_empty() {
+            let child = McTree::select(&node.borrow(), config);
+            child.borrow_mut().N += 1;
+            child.borrow_mut().W -= VIRTUAL_LOSS;
+            path.push(child.clone());
+            let mov = child.borrow().mov.clone().unwrap();
+            let mover = pos.next_move.unwrap();
+            pos.make_move(&mov);
+            let finish = self.history.borrow_mut().push(PositionAndMove::from(pos.clone(), mov));
+            pos.state = self.history.borrow_mut().last().borrow().pos.state.clone();
+            if let Some(finish) = finish {
+                leaf.value = Some(finish_value(&finish, mover));
+                break;
+            }
+            node = child;
+        }
+        if leaf.value.is_none() {
+            let move_list = pos.get_move_list_cached();
+            let moves = move_list.as_ref().as_ref().unwrap().list.clone();
+            leaf.request = Some(requests.len());
+            requests.push(EvalRequest { pos, moves });
+        }
+        self.history.borrow_mut().cut_to(hist_len);
+        leaf.path = path;
+        leaf
+    }
+
+    // takes the virtual losses back and adds `value`, for the side that made the last move
+    fn back_up(&mut self, leaf: &Leaf, mut value: f64) {
+        for node in leaf.path.iter().rev() {
+            node.borrow_mut().W += VIRTUAL_LOSS + value;
+            value = -value;
+        }
+    }
+
+    // Dirichlet noise mixed into the priors of the root childs
+    pub fn add_root_noise(&mut self, config: &PuctConfig, rng: &mut impl Rng) {
+        let root = self.root.borrow();
+        let noise = dirichlet(config.dirichlet_alpha, root.childs.len(), rng);
+        for (child, eta) in root.childs.iter().zip(noise) {
+            let mut child = child.borrow_mut();
+            child.P = ((1.0 - config.noise_fraction) * child.P as f64 + config.noise_fraction * eta) as f32;
+        }
+    }
+
+    // PUCT search: childs are expanded with the policy of `evaluator` and valued by its
+    // value, batch by batch. Returns the child of the final selection of the MCTS config.
+    pub fn search_puct(&mut self, evaluator: &mut dyn Evaluator, config: &PuctConfig, passes: i32,
+                       rng: &mut impl Rng) -> Option<Rc<RefCell<Node>>> {
+        if self.root.borrow().childs.is_empty() {
+            let mut pos = self.pos.clone();
+            let move_list = pos.get_move_list_cached();
+            let moves = move_list.as_ref().as_ref().unwrap().list.clone();
+            if moves.is_empty() { return None; }
+            let evaluation = evaluator.evaluate(&[EvalRequest { pos, moves: moves.clone() }]);
+            let root = self.root.clone();
+            self.expand_with(&root, moves, &evaluation[0].policy);
+            self.root_noised = false;
+        }
+        if config.root_noise && !self.root_noised {
+            self.add_root_noise(config, rng);
+            self.root_noised = true;
+        }
+        let mut pass = 0;
+        while pass < passes {
+            let mut leaves: Vec<Leaf> = vec![];
+            let mut requests = vec![];
+            while leaves.len() < config.batch_size.max(1) && pass + (leaves.len() as i32) < passes {
+                let leaf = self.select_leaf(config, &mut requests);
+                // a node already waiting in the batch ends it
+                let waiting = leaf.request.is_some() && leaves.iter().any(|x| x.request.is_some()
+                    && leaf.path.last().zip(x.path.last()).is_some_and(|(a, b)| Rc::ptr_eq(a, b)));
+                if waiting {
+                    requests.pop();
+                    self.root.borrow_mut().N -= 1;
+                    for node in &leaf.path {
+                        node.borrow_mut().N -= 1;
+                        node.borrow_mut().W += VIRTUAL_LOSS;
+                    }
+                    break;
+                }
+                leaves.push(leaf);
+            }
+            let evaluations = if requests.is_empty() { vec![] } else { evaluator.evaluate(&requests) };
+            for leaf in &leaves {
+                let value = match (leaf.value, leaf.request) {
+                    (Some(value), _) => value,
+                    (None, Some(i)) => {
+                        let node = leaf.path.last().unwrap_or(&self.root).clone();
+                        self.expand_with(&node, requests[i].moves.clone(), &evaluations[i].policy);
+                        // the value is the one of the side to move after the last move
+                        -evaluations[i].value as f64
+                    }
+                    _ => 0.0
+                };
+                self.back_up(leaf, value);
+            }
+            pass += leaves.len() as i32;
+            if self.nodes > self.max_nodes { self.prune(); }
+        }
+        self.best_child()
+    }
+
+    // share of the visits of each root move, the policy target of self-play
+    pub fn visit_distribution(&self) -> Vec<(MoveItem, f64)> {
+        let root = self.root.borrow();
+        let total: i64 = root.childs.iter().map(|x| x.borrow().N).sum();
+        root.childs.iter().map(|x| {
+            let x = x.borrow();
+            (x.mov.clone().unwrap(), if total > 0 { x.N as f64 / total as f64 } else { 0.0 })
+        }).collect()
+    }
+}
+
+impl Game {
+    // PUCT search with `evaluator` on the tree of the game, kept like for best_move_mcts
+    pub fn best_move_puct(&mut self, evaluator: &mut dyn Evaluator, config: &PuctConfig) -> BestPos {
+        let key = self.current_position.zobrist();
+        if self.tree.as_ref().is_none_or(|x| x.position().zobrist() != key) {
+            self.tree = Some(McTree::with_config(self.current_position.clone(), self.position_history.clone(),
+                                                 self.mcts_config.clone()));
+        }
+        let tree = self.tree.as_mut().unwrap();
+        match tree.search_puct(evaluator, config, config.passes, &mut rand::thread_rng()) {
+            Some(node) => {
+                let node = node.borrow();
+                let score = (node.value() * 1000.0) as i32 * self.sign();
+                self.best_pos(score, vec![node.get_move().unwrap()])
+            }
+            None => self.best_pos(0, vec![])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::game::Game;
+    use crate::mcts::McTree;
+    use crate::puct::{dirichlet, EvalRequest, Evaluation, Evaluator, PuctConfig, StaticEvaluator};
+
+    // counts the requests, half the prior on the first move
+    struct Counting {
+        batches: Vec<usize>,
+    }
+
+    impl Evaluator for Counting {
+        fn evaluate(&mut self, requests: &[EvalRequest]) -> Vec<Evaluation> {
+            self.batches.push(requests.len());
+            requests.iter().map(|x| {
+                let mut policy = vec![0.5 / x.moves.len() as f32; x.moves.len()];
+                policy[0] += 0.5;
+                Evaluation { policy, value: 0.0 }
+            }).collect()
+        }
+    }
+
+    fn tree(fen: &str) -> (Game, McTree) {
+        let mut game = Game::new(8);
+        game.set_fen(fen).unwrap();
+        let tree = McTree::new(game.current_position.clone(), game.position_history.clone());
+        (game, tree)
+    }
+
+    #[test]
+    fn noise() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for alpha in [0.03, 0.3, 2.0] {
+            let sample = dirichlet(alpha, 7, &mut rng);
+            assert_eq!(sample.len(), 7);
+            assert!((sample.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            assert!(sample.iter().all(|x| *x >= 0.0));
+        }
+        // the mean of a share is 1 / n
+        let mean = (0..2000).map(|_| dirichlet(0.3, 4, &mut rng)[0]).sum::<f64>() / 2000.0;
+        assert!((mean - 0.25).abs() < 0.03, "{}", mean);
+    }
+
+    #[test]
+    fn batches() {
+        let (game, mut tree) = tree("W:W21-32:B1-12");
+        let history = game.position_history.borrow().len();
+        let mut evaluator = Counting { batches: vec![] };
+        let config = PuctConfig { batch_size: 4, ..PuctConfig::default() };
+        let best = tree.search_puct(&mut evaluator, &config, 100, &mut StdRng::seed_from_u64(2)).unwrap();
+        assert_eq!(game.position_history.borrow().len(), history);
+        assert_eq!(evaluator.batches[0], 1);
+        assert!(evaluator.batches[1..].iter().all(|x| (1..=4).contains(x)));
+        assert!(evaluator.batches.iter().any(|x| *x > 1));
+        // every pass is a visit and the virtual losses are all taken back
+        let root = tree.root();
+        assert_eq!(root.borrow().visits(), 100);
+        let childs: i64 = root.borrow().childs.iter().map(|x| x.borrow().visits()).sum();
+        assert_eq!(childs, 100);
+        assert!(root.borrow().childs.iter().all(|x| x.borrow().value().abs() < 1e-9));
+        // the prior leads the search
+        assert!(std::rc::Rc::ptr_eq(&best, &root.borrow().childs[0]));
+        let distribution = tree.visit_distribution();
+        assert!((distribution.iter().map(|x| x.1).sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_eq!(tree.nodes(), root.borrow().size());
+    }
+
+    #[test]
+    fn root_noise() {
+        let (_, mut tree) = tree("W:W21-32:B1-12");
+        let config = PuctConfig { root_noise: true, noise_fraction: 0.5, ..PuctConfig::default() };
+        tree.search_puct(&mut Counting { batches: vec![] }, &config, 1, &mut StdRng::seed_from_u64(3));
+        let priors: Vec<f32> = tree.root().borrow().childs.iter().map(|x| x.borrow().prior()).collect();
+        assert!((priors.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        // half of each prior is kept, the other half is noise
+        let base = [0.5 + 0.5 / 7.0, 0.5 / 7.0];
+        assert!(priors[0] >= base[0] / 2.0 && priors[1..].iter().all(|x| *x >= base[1] / 2.0));
+        assert!(priors[1..].iter().any(|x| (x - base[1]).abs() > 1e-3));
+        // the next searches of the same root keep its noise
+        tree.search_puct(&mut Counting { batches: vec![] }, &config, 1, &mut StdRng::seed_from_u64(4));
+        let again: Vec<f32> = tree.root().borrow().childs.iter().map(|x| x.borrow().prior()).collect();
+        assert_eq!(again, priors);
+    }
+
+    #[test]
+    fn finds_win() {
+        // 22x15 takes the last piece
+        let (_, mut tree) = tree("W:WK22,K29:B18");
+        let best = tree.search_puct(&mut StaticEvaluator, &PuctConfig::default(), 64, &mut StdRng::seed_from_u64(4));
+        assert_eq!(best.unwrap().borrow().value(), 1.0);
+        let mut game = Game::new(8);
+        game.set_start_position();
+        let config = PuctConfig { passes: 50, ..PuctConfig::default() };
+        let best = game.best_move_puct(&mut StaticEvaluator, &config);
+        assert_eq!(best.pv.len(), 1);
+        assert_eq!(game.tree.as_ref().unwrap().root().borrow().visits(), 50);
+    }
+}
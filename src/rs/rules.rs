@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::color::Color;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub enum Variant {
+    Russian,
+    International,
+    Brazilian,
+    Pool,
+    English,
+}
+
+// which draw rules finish_check applies; limits are counted in plies like the Russian ones
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct DrawRules {
+    pub repetitions: u8,
+    // only kings moved, no strikes
+    pub kings_only_moves: Option<usize>,
+    // no strikes and no men moved, by any pieces (the 40-move rule of the English checkers)
+    pub quiet_moves: Option<usize>,
+    // triangle, equal power and main road rules of the Russian draughts
+    pub russian_endings: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RuleSet {
+    pub variant: Variant,
+    pub size: i8,
+    pub first_move: Color,
+    // kings move and strike along the whole diagonal, otherwise one square
+    pub flying_kings: bool,
+    pub men_capture_backward: bool,
+    // only the strikes taking the most pieces are legal
+    pub majority_capture: bool,
+    // a man passing the king row during a strike goes on as a king, otherwise it is
+    // crowned only if the strike ends there
+    pub promote_mid_capture: bool,
+    pub draw_rules: DrawRules,
+    // GameType tag of PDN
+    pub pdn_game_type: u8,
+}
+
+impl RuleSet {
+    pub fn new(variant: Variant) -> RuleSet {
+        match variant {
+            Variant::Russian => RuleSet::russian(8),
+            Variant::International => RuleSet {
+                variant,
+                size: 10,
+                majority_capture: true,
+                promote_mid_capture: false,
+                draw_rules: DrawRules { repetitions: 3, kings_only_moves: Some(50), quiet_moves: None, russian_endings: false },
+                pdn_game_type: 20,
+                ..RuleSet::russian(10)
+            },
+            Variant::Brazilian => RuleSet {
+                variant,
+                majority_capture: true,
+                promote_mid_capture: false,
+                draw_rules: DrawRules { repetitions: 3, kings_only_moves: Some(40), quiet_moves: None, russian_endings: false },
+                pdn_game_type: 26,
+                ..RuleSet::russian(8)
+            },
+            Variant::Pool => RuleSet {
+                variant,
+                first_move: Color::Black,
+                promote_mid_capture: false,
+                draw_rules: DrawRules { repetitions: 3, kings_only_moves: None, quiet_moves: None, russian_endings: false },
+                pdn_game_type: 23,
+                ..RuleSet::russian(8)
+            },
+            Variant::English => RuleSet {
+                variant,
+                first_move: Color::Black,
+                flying_kings: false,
+                men_capture_backward: false,
+                // men strike forward only, so a strike ends where the man is crowned
+                promote_mid_capture: false,
+                draw_rules: DrawRules { repetitions: 3, kings_only_moves: None, quiet_moves: Some(80), russian_endings: false },
+                pdn_game_type: 21,
+                ..RuleSet::russian(8)
+            },
+        }
+    }
+
+    // Russian rules on any board size, the rules Game::new(size) plays by
+    pub fn russian(size: i8) -> RuleSet {
+        RuleSet {
+            variant: Variant::Russian,
+            size,
+            first_move: Color::White,
+            flying_kings: true,
+            men_capture_backward: true,
+            majority_capture: false,
+            promote_mid_capture: true,
+            draw_rules: DrawRules { repetitions: 3, kings_only_moves: Some(15), quiet_moves: None, russian_endings: true },
+            pdn_game_type: 25,
+        }
+    }
+
+    pub fn from_pdn_game_type(game_type: u8) -> Option<RuleSet> {
+        [Variant::Russian, Variant::International, Variant::Brazilian, Variant::Pool, Variant::English]
+            .into_iter().map(RuleSet::new).find(|x| x.pdn_game_type == game_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::color::Color;
+    use crate::game::Game;
+    use crate::notation::Notation;
+    use crate::PositionHistory::FinishType;
+    use crate::position::Position;
+    use crate::position_environment::PositionEnvironment;
+    use crate::rules::{RuleSet, Variant};
+
+    fn position(variant: Variant, fen: &str) -> Position {
+        let env = Arc::new(PositionEnvironment::with_rules(RuleSet::new(variant)));
+        Position::from_fen(env, fen).unwrap()
+    }
+
+    #[test]
+    fn start_positions() {
+        for (variant, color, pieces) in [(Variant::Russian, Color::White, 12), (Variant::International, Color::White, 20),
+                                         (Variant::Pool, Color::Black, 12), (Variant::English, Color::Black, 12)] {
+            let mut game = Game::with_variant(variant);
+            game.set_start_position();
+            assert_eq!(game.variant(), variant);
+            assert_eq!(game.current_position.next_move, Some(color));
+            assert_eq!(game.current_position.state.white.simple, pieces);
+            assert_eq!(game.current_position.state.black.simple, pieces);
+        }
+    }
+
+    #[test]
+    fn english_perft() {
+        let env = Arc::new(PositionEnvironment::with_rules(RuleSet::new(Variant::English)));
+        let mut pos = Position::start_position(env);
+        let expected: [u64; 8] = [1, 7, 49, 302, 1469, 7361, 36768, 179740];
+        for (depth, nodes) in expected.iter().enumerate() {
+            assert_eq!(pos.perft(depth as u32), *nodes, "depth {}", depth);
+            if depth < 6 {
+                assert_eq!(pos.perft_by_cells(depth as u32), *nodes, "depth {}", depth);
+            }
+        }
+    }
+
+    #[test]
+    fn short_kings_and_forward_captures() {
+        // a king in the corner slides along the main road only with flying kings
+        assert_eq!(position(Variant::Russian, "W:WK29:B1").get_move_list(false).list.len(), 7);
+        assert_eq!(position(Variant::English, "W:WK29:B1").get_move_list(false).list.len(), 1);
+        // a man with an enemy behind it
+        let fen = "W:W18:B22";
+        assert!(position(Variant::Russian, fen).get_move_list(false).list[0].strike.is_some());
+        assert!(position(Variant::English, fen).get_move_list(false).list[0].strike.is_none());
+    }
+
+    #[test]
+    fn international_perft() {
+        let env = Arc::new(PositionEnvironment::with_rules(RuleSet::new(Variant::International)));
+        let mut pos = Position::start_position(env);
+        let expected: [u64; 7] = [1, 9, 81, 658, 4265, 27117, 167140];
+        for (depth, nodes) in expected.iter().enumerate() {
+            assert_eq!(pos.perft(depth as u32), *nodes, "depth {}", depth);
+        }
+        assert_eq!(pos.perft_by_cells(5), 27117);
+    }
+
+    #[test]
+    fn majority_capture() {
+        let paths = |variant: Variant, fen: &str| {
+            let mut pos = position(variant, fen);
+            let list = pos.get_move_list(true);
+            let mut by_cells = pos.get_move_list_by_cells(true).list.iter()
+                .map(|x| (x.text(&pos.environment, Notation::Algebraic).to_string(), x.is_king_move()))
+                .collect::<Vec<_>>();
+            let mut res: Vec<_> = list.list.iter()
+                .map(|x| (x.text(&pos.environment, Notation::Algebraic).to_string(), x.is_king_move()))
+                .collect();
+            res.sort();
+            by_cells.sort();
+            assert_eq!(res, by_cells);
+            res
+        };
+        let owned = |x: &[(&str, bool)]| x.iter().map(|(a, b)| (a.to_string(), *b)).collect::<Vec<_>>();
+        let fen = "W:W22:B17,18,10";
+        assert_eq!(paths(Variant::Russian, fen), owned(&[("c3:a5", false), ("c3:e5:c7", false)]));
+        assert_eq!(paths(Variant::Brazilian, fen), owned(&[("c3:e5:c7", false)]));
+        // a man crowned on d8 goes on striking as a king in Russian draughts only
+        let fen = "W:W11:B7,9";
+        assert_eq!(paths(Variant::Russian, fen), owned(&[("f6:d8:a5", true)]));
+        assert_eq!(paths(Variant::Brazilian, fen), owned(&[("f6:d8", true)]));
+        // passing the king row as a man, not crowned at the end
+        let fen = "W:W11:B6,7";
+        assert_eq!(paths(Variant::Russian, fen), owned(&[("f6:d8:a5", true), ("f6:d8:b6", true)]));
+        assert_eq!(paths(Variant::Brazilian, fen), owned(&[("f6:d8:b6", false)]));
+        let mut pos = position(Variant::Brazilian, fen);
+        let mov = pos.get_move_list(false).list[0].clone();
+        pos.make_move(&mov);
+        assert_eq!(pos.to_fen(), "B:W9:B");
+    }
+
+    #[test]
+    fn english_promotion_ends_strike() {
+        let fen = "W:W11:B6,7";
+        let texts = |variant: Variant| {
+            let mut pos = position(variant, fen);
+            let list = pos.get_move_list(true);
+            list.list.iter().map(|x| (x.text(&pos.environment, Notation::Algebraic).to_string(), x.is_king_move()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(texts(Variant::English), vec![("f6:d8".to_string(), true)]);
+        assert_eq!(texts(Variant::Pool), vec![("f6:d8:b6".to_string(), false)]);
+    }
+
+    #[test]
+    fn english_quiet_moves_draw() {
+        let mut rules = RuleSet::new(Variant::English);
+        rules.draw_rules.repetitions = u8::MAX;
+        let mut game = Game::with_rules(rules);
+        game.set_fen("W:WK29,24:BK4,9").unwrap();
+        let moves = ["29-25", "4-8", "25-29", "8-4"];
+        for ply in 0..80 {
+            assert_eq!(game.position_history.borrow_mut().finish_check(), None, "ply {}", ply);
+            let move_item = game.find_move(moves[ply % 4]).unwrap();
+            game.make_move_by_move_item(&move_item);
+        }
+        assert!(matches!(game.position_history.borrow_mut().finish_check(), Some(FinishType::Draw6)));
+        // a man move starts the count again
+        let move_item = game.find_move("24-20").unwrap();
+        game.make_move_by_move_item(&move_item);
+        assert_eq!(game.position_history.borrow_mut().finish_check(), None);
+    }
+
+    #[test]
+    fn international_kings_only_draw() {
+        let mut rules = RuleSet::new(Variant::International);
+        rules.draw_rules.repetitions = u8::MAX;
+        let mut game = Game::with_rules(rules);
+        game.set_fen("W:WK46,50:BK1").unwrap();
+        let moves = ["46-41", "1-7", "41-46", "7-1"];
+        for ply in 0..50 {
+            assert_eq!(game.position_history.borrow_mut().finish_check(), None, "ply {}", ply);
+            let move_item = game.find_move(moves[ply % 4]).unwrap();
+            game.make_move_by_move_item(&move_item);
+        }
+        assert!(matches!(game.position_history.borrow_mut().finish_check(), Some(FinishType::Draw1)));
+        // a man move starts the count again
+        let move_item = game.find_move("50-44").unwrap();
+        game.make_move_by_move_item(&move_item);
+        assert_eq!(game.position_history.borrow_mut().finish_check(), None);
+    }
+
+    #[test]
+    fn pdn_game_type() {
+        for variant in [Variant::Russian, Variant::International, Variant::Brazilian, Variant::Pool, Variant::English] {
+            let rules = RuleSet::new(variant);
+            assert_eq!(RuleSet::from_pdn_game_type(rules.pdn_game_type), Some(rules));
+        }
+        assert_eq!(RuleSet::from_pdn_game_type(1), None);
+    }
+}
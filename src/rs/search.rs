@@ -0,0 +1,499 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use serde::Serialize;
+use ts_rs::TS;
+use wasm_bindgen::prelude::*;
+use crate::color::Color;
+use crate::game::{BestPos, Game};
+use crate::moves_list::MoveItem;
+use crate::negamax::INFINITY;
+use crate::notation::Notation;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::negamax::Searcher;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::transposition::Bound;
+
+pub const MAX_DEPTH: i16 = 32;
+
+// milliseconds of wall-clock time, Instant is not available in the browser
+#[cfg(target_arch = "wasm32")]
+pub fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |x| x.as_secs_f64() * 1000.0)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchLimits {
+    pub max_depth: i16,
+    pub time_ms: Option<f64>,
+    pub max_nodes: Option<u64>,
+    // root splitting over this many threads on native builds
+    pub threads: usize,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        SearchLimits { max_depth: MAX_DEPTH, time_ms: Some(1000.0), max_nodes: None, threads: 1 }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, TS)]
+#[ts(export)]
+pub struct SearchStats {
+    // nodes of best_move, passes of the MCTS
+    pub nodes: u64,
+    // last fully searched depth, the deepest pass of the MCTS
+    pub depth: i16,
+    pub cutoffs: u64,
+    pub tt_hits: u64,
+    pub time_ms: f64,
+    // nodes per second
+    pub nps: u64,
+}
+
+impl SearchStats {
+    pub fn set_time(&mut self, start_ms: f64) {
+        self.time_ms = now_ms() - start_ms;
+        self.nps = if self.time_ms > 0.0 { (self.nodes as f64 * 1000.0 / self.time_ms) as u64 } else { 0 };
+    }
+}
+
+// progress report passed to the info callback
+#[derive(Clone, Debug, Default, Serialize, TS)]
+#[ts(export)]
+pub struct SearchInfo {
+    pub stats: SearchStats,
+    // white advantage if positive, for MCTS the win rate of the side to move in thousandths
+    pub score: i32,
+    // algebraic notation
+    pub pv: Vec<String>,
+}
+
+pub type InfoCallback = Box<dyn FnMut(&SearchInfo)>;
+
+// search of the strikes left at the leaves of the negamax search
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuiescenceConfig {
+    pub enabled: bool,
+    // plies of strikes searched past the leaf
+    pub max_depth: i16,
+}
+
+impl Default for QuiescenceConfig {
+    fn default() -> Self {
+        QuiescenceConfig { enabled: true, max_depth: 8 }
+    }
+}
+
+// budget of the running search, best_move checks it on every node
+#[derive(Clone, Debug, Default)]
+pub struct SearchControl {
+    pub stats: SearchStats,
+    pub aborted: bool,
+    active: bool,
+    start: f64,
+    deadline: Option<f64>,
+    max_nodes: Option<u64>,
+    // nodes of all the workers of a parallel search, for their common node limit
+    shared_nodes: Option<Arc<AtomicU64>>,
+}
+
+impl SearchControl {
+    pub fn new(limits: &SearchLimits) -> SearchControl {
+        let start = now_ms();
+        SearchControl {
+            start,
+            deadline: limits.time_ms.map(|x| start + x),
+            max_nodes: limits.max_nodes,
+            ..SearchControl::default()
+        }
+    }
+
+    pub fn out_of_time(&self) -> bool {
+        self.deadline.is_some_and(|x| now_ms() >= x)
+    }
+
+    // counts a node, true if the search has to stop
+    #[inline]
+    pub fn tick(&mut self) -> bool {
+        self.stats.nodes += 1;
+        if !self.active || self.aborted { return self.aborted; }
+        let nodes = match self.shared_nodes {
+            Some(ref shared) => shared.fetch_add(1, Ordering::Relaxed) + 1,
+            None => self.stats.nodes
+        };
+        // the clock is read once per 1024 nodes
+        if self.max_nodes.is_some_and(|x| nodes >= x)
+            || (self.stats.nodes & 1023 == 0 && self.out_of_time()) {
+            self.aborted = true;
+        }
+        self.aborted
+    }
+}
+
+impl Game {
+    // Searches depth 1, 2, ... and returns the best move of the last completed depth;
+    // the first depth is always completed so there is a move to return. Depths after
+    // the first start with an aspiration window around the score of the previous one.
+    pub fn best_move_iterative(&mut self, limits: &SearchLimits) -> BestPos {
+        self.search = SearchControl::new(limits);
+        self.ordering.new_search();
+        #[cfg(not(target_arch = "wasm32"))]
+        if limits.threads > 1 { self.prepare_workers(limits.threads); }
+        let sign = if self.current_position.next_move == Some(Color::White) { 1 } else { -1 };
+        let mut best: Option<BestPos> = None;
+        for depth in 1..=limits.max_depth.max(1) {
+            self.search.active = best.is_some();
+            let guess = best.as_ref().map(|x| x.deep_eval * sign);
+            let (score, pv) = self.search_root(depth + 1, guess, limits.threads);
+            if self.search.aborted { break; }
+            let found = self.best_pos(score * sign, pv);
+            self.search.stats.depth = depth;
+            self.search.stats.set_time(self.search.start);
+            if let Some(ref mut on_info) = self.on_info {
+                let environment = self.current_position.environment.clone();
+                on_info(&SearchInfo {
+                    stats: self.search.stats.clone(),
+                    score: found.deep_eval,
+                    pv: found.pv.iter().map(|x| x.text(&environment, Notation::Algebraic).to_string()).collect(),
+                });
+            }
+            best = Some(found);
+            if self.search.out_of_time() || self.search.max_nodes.is_some_and(|x| self.search.stats.nodes >= x) {
+                break;
+            }
+        }
+        self.search.active = false;
+        self.search.stats.set_time(self.search.start);
+        best.unwrap()
+    }
+
+    // One depth of the iterative search, in a window around `guess` if there is one,
+    // split over the threads on native builds.
+    fn search_root(&mut self, depth: i16, guess: Option<i32>, threads: usize) -> (i32, Vec<MoveItem>) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if threads > 1 {
+            return self.root_parallel(depth, threads);
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = threads;
+        let mut searcher = self.searcher();
+        let score = match guess {
+            Some(guess) => searcher.aspiration(depth, guess),
+            None => searcher.root(depth, -INFINITY, INFINITY),
+        };
+        (score, searcher.pv())
+    }
+
+    // called after every completed depth of the iterative search
+    pub fn set_info_callback(&mut self, on_info: impl FnMut(&SearchInfo) + 'static) {
+        self.on_info = Some(Box::new(on_info));
+    }
+
+    pub fn search_stats(&self) -> &SearchStats {
+        &self.search.stats
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Game {
+    // pool of `threads` threads and fresh tables for the workers of the next searches,
+    // the pool is only built again for another number of threads
+    pub(crate) fn prepare_workers(&mut self, threads: usize) {
+        if self.pool.as_ref().is_none_or(|x| x.current_num_threads() != threads) {
+            self.pool = Some(rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap());
+        }
+        self.worker_tts = (0..threads).map(|_| self.tt.empty_copy()).collect();
+    }
+
+    // Root splitting: the first root move is searched alone to get a bound, the others
+    // by `threads` workers taking them in turn, each with its own copy of the position
+    // and the move ordering and its own table, kept over the depths of the search. A move
+    // only has to beat the best score found so far. The node limit counts the nodes of all
+    // the workers. Returns the score for the side to move and the line, like Searcher::root.
+    pub(crate) fn root_parallel(&mut self, mut depth: i16, threads: usize) -> (i32, Vec<MoveItem>) {
+        use std::sync::atomic::{AtomicI32, AtomicUsize};
+        use std::sync::Mutex;
+        if self.worker_tts.len() != threads { self.prepare_workers(threads); }
+        let key = self.current_position.zobrist();
+        let move_list = self.current_position.get_move_list_cached();
+        let list = &move_list.as_ref().as_ref().unwrap().list;
+        if list.len() == 1 { depth += 1; }
+        // the best move of the previous depth goes first and sets the bound for the others
+        let mut order: Vec<usize> = (0..list.len()).collect();
+        if let Some(i) = self.tt.probe(key).and_then(|x| x.best_move()).filter(|&i| i < list.len()) {
+            order[..=i].rotate_right(1);
+        }
+        let (first_score, first_line) = self.searcher().root_move(&list[order[0]], depth, -INFINITY);
+        if self.search.aborted { return (first_score, first_line); }
+        let best = AtomicI32::new(first_score);
+        let next = AtomicUsize::new(1);
+        let path = self.history_keys();
+        let quiet_moves = self.position_history.borrow().quiet_moves();
+        let mut control = self.search.clone();
+        control.shared_nodes = Some(Arc::new(AtomicU64::new(self.search.stats.nodes)));
+        // scores and lines of the moves, counters of the workers
+        let (results, worker_stats) = (Mutex::new(vec![]), Mutex::new(vec![]));
+        let pool = self.pool.as_ref().unwrap();
+        let (position, ordering, quiescence) = (&self.current_position, &self.ordering, &self.quiescence);
+        let (tablebase, network) = (self.tablebase.as_deref(), self.network.as_deref());
+        pool.scope(|scope| {
+            for tt in self.worker_tts.iter_mut() {
+                let (path, results, worker_stats, best, next, order) =
+                    (&path, &results, &worker_stats, &best, &next, &order);
+                let mut control = control.clone();
+                scope.spawn(move |_| {
+                    let (mut pos, mut ordering) = (position.clone(), ordering.clone());
+                    let before = control.stats.clone();
+                    loop {
+                        let n = next.fetch_add(1, Ordering::Relaxed);
+                        if n >= order.len() || control.aborted { break; }
+                        let i = order[n];
+                        let alpha = best.load(Ordering::Relaxed);
+                        let mut searcher = Searcher::new(&mut pos, tt, &mut ordering, &mut control, quiescence,
+                                                         path.clone())
+                            .with_tablebase(tablebase).with_network(network).with_quiet_moves(quiet_moves);
+                        let (score, line) = searcher.root_move(&list[i], depth, alpha);
+                        let exact = score > alpha && !control.aborted;
+                        if exact { best.fetch_max(score, Ordering::Relaxed); }
+                        results.lock().unwrap().push((i, score, exact, line));
+                    }
+                    worker_stats.lock().unwrap().push((SearchStats {
+                        nodes: control.stats.nodes - before.nodes,
+                        cutoffs: control.stats.cutoffs - before.cutoffs,
+                        tt_hits: control.stats.tt_hits - before.tt_hits,
+                        ..SearchStats::default()
+                    }, control.aborted));
+                });
+            }
+        });
+        for (stats, aborted) in worker_stats.into_inner().unwrap() {
+            self.search.stats.nodes += stats.nodes;
+            self.search.stats.cutoffs += stats.cutoffs;
+            self.search.stats.tt_hits += stats.tt_hits;
+            self.search.aborted |= aborted;
+        }
+        let mut res = (order[0], first_score, first_line);
+        for (i, score, exact, line) in results.into_inner().unwrap() {
+            if exact && score > res.1 {
+                res = (i, score, line);
+            }
+        }
+        let (i, score, line) = res;
+        if !self.search.aborted {
+            self.tt.store(key, depth, Bound::Exact, score, Some(i));
+        }
+        (score, line)
+    }
+
+    // fixed depth search of best_move(max_depth, ...) split over `threads` threads
+    pub fn best_move_parallel(&mut self, max_depth: i16, threads: usize) -> BestPos {
+        self.ordering.new_search();
+        self.prepare_workers(threads);
+        let (score, pv) = self.root_parallel(max_depth + 1, threads);
+        self.best_pos(score * self.sign(), pv)
+    }
+
+    // threads of get_best_move_iterative, the available cores if 0
+    pub fn set_threads(&mut self, threads: usize) {
+        self.search_limits.threads = if threads > 0 { threads } else { rayon::current_num_threads() };
+    }
+}
+
+#[wasm_bindgen]
+impl Game {
+    // limit of get_best_move_iterative, no limit if not positive
+    #[wasm_bindgen]
+    pub fn set_time_limit(&mut self, time_ms: f64) {
+        self.search_limits.time_ms = if time_ms > 0.0 { Some(time_ms) } else { None };
+    }
+
+    // limit of get_best_move_iterative, no limit if 0
+    #[wasm_bindgen]
+    pub fn set_node_limit(&mut self, nodes: u32) {
+        self.search_limits.max_nodes = if nodes > 0 { Some(nodes as u64) } else { None };
+    }
+
+    #[wasm_bindgen]
+    pub fn get_best_move_iterative(&mut self) -> JsValue {
+        let finish = self.position_history.borrow_mut().finish_check();
+        let limits = self.search_limits.clone();
+        let res = match finish {
+            Some(finish) => serde_wasm_bindgen::to_value(&finish),
+            None => {
+                let best = self.book_move().unwrap_or_else(|| self.best_move_iterative(&limits));
+                serde_wasm_bindgen::to_value(&best)
+            }
+        };
+        res.unwrap_or(JsValue::UNDEFINED)
+    }
+
+    // strikes searched past max_depth, up to `max_depth` plies; the table is cleared
+    // as its scores were found with the other setting
+    #[wasm_bindgen]
+    pub fn set_quiescence(&mut self, enabled: bool, max_depth: i16) {
+        self.quiescence = QuiescenceConfig { enabled, max_depth };
+        self.tt.clear();
+    }
+
+    // killer and history move ordering, off to order by evaluating every child as before
+    #[wasm_bindgen]
+    pub fn set_heuristic_ordering(&mut self, enabled: bool) {
+        self.ordering.enabled = enabled;
+    }
+
+    // depth completed by the last iterative search
+    #[wasm_bindgen(getter)]
+    pub fn search_depth(&self) -> i16 {
+        self.search.stats.depth
+    }
+
+    // `on_info(info)` gets a SearchInfo object after every completed depth
+    #[wasm_bindgen]
+    pub fn set_info_callback_js(&mut self, on_info: js_sys::Function) {
+        self.set_info_callback(move |info| {
+            if let Ok(info) = serde_wasm_bindgen::to_value(info) {
+                let _ = on_info.call1(&JsValue::NULL, &info);
+            }
+        });
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_info_callback(&mut self) {
+        self.on_info = None;
+    }
+
+    #[wasm_bindgen]
+    pub fn get_search_stats(&self) -> JsValue {
+        serde_wasm_bindgen::to_value(&self.search.stats).unwrap_or(JsValue::UNDEFINED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::negamax::INFINITY;
+    use crate::search::{now_ms, SearchInfo, SearchLimits};
+
+    fn game() -> Game {
+        let mut game = Game::new(8);
+        game.set_fen("W:W17,21,22,23,24,25,27,28,29,30,31:B1,2,3,4,5,6,7,8,10,11,12").unwrap();
+        game
+    }
+
+    #[test]
+    fn iterative_matches_fixed_depth() {
+        let mut game = game();
+        let limits = SearchLimits { max_depth: 4, time_ms: None, max_nodes: None, threads: 1 };
+        let iterative = game.best_move_iterative(&limits);
+        assert_eq!(game.search.stats.depth, 4);
+        assert!(!game.search.aborted);
+        let mut fixed = self::game();
+        let fixed = fixed.best_move(4, i32::MIN, i32::MAX, 0);
+        assert_eq!(iterative.deep_eval, fixed.deep_eval);
+    }
+
+    #[test]
+    fn node_budget() {
+        let mut game = game();
+        let fen = game.get_fen();
+        let history = game.position_history.borrow().len();
+        let limits = SearchLimits { max_depth: 30, time_ms: None, max_nodes: Some(5000), threads: 1 };
+        let best = game.best_move_iterative(&limits);
+        assert!(best.get_move_item().from() < 32);
+        assert!(game.search.stats.depth >= 1 && game.search.stats.depth < 30);
+        // an aborted iteration leaves the game as it was
+        assert_eq!(game.get_fen(), fen);
+        assert_eq!(game.position_history.borrow().len(), history);
+    }
+
+    #[test]
+    fn time_budget() {
+        let mut game = game();
+        let limits = SearchLimits { max_depth: 30, time_ms: Some(200.0), max_nodes: None, threads: 1 };
+        let start = now_ms();
+        game.best_move_iterative(&limits);
+        assert!(now_ms() - start < 2000.0);
+        assert!(game.search.stats.depth >= 1);
+    }
+
+    #[test]
+    fn info_callback() {
+        let mut game = game();
+        let infos = Rc::new(RefCell::new(vec![]));
+        let sink = infos.clone();
+        game.set_info_callback(move |info: &SearchInfo| sink.borrow_mut().push(info.clone()));
+        let limits = SearchLimits { max_depth: 4, time_ms: None, max_nodes: None, threads: 1 };
+        let best = game.best_move_iterative(&limits);
+        let infos = infos.borrow();
+        assert_eq!(infos.iter().map(|x| x.stats.depth).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert!(infos.windows(2).all(|x| x[0].stats.nodes < x[1].stats.nodes));
+        let last = infos.last().unwrap();
+        assert_eq!(last.score, best.deep_eval);
+        assert_eq!(last.pv.len(), best.pv.len());
+        assert_eq!(game.search_stats().nodes, last.stats.nodes);
+        assert!(last.stats.cutoffs > 0);
+    }
+
+    #[test]
+    fn quiescence() {
+        let mut game = Game::new(8);
+        game.set_fen("W:W22,29:B8,11,18").unwrap();
+        let eval = game.searcher().quiesce(-INFINITY, INFINITY, 0, 0);
+        // c3:e5 f6:d4 played out
+        let mut pos = game.current_position.clone();
+        for text in ["c3:e5", "f6:d4"] {
+            let mov = pos.get_move_list(true).find_move(&game.position_environment, text).unwrap().clone();
+            pos.make_move(&mov);
+        }
+        assert_eq!(eval, pos.evaluate());
+        assert_eq!(game.get_fen(), "W:W22,29:B8,11,18");
+
+        // c3-b4 and c3-d4 both hang the man in two ways (so no forced move extension),
+        // seen at the leaf only with quiescence
+        let leaf_eval = |enabled: bool| {
+            let mut game = Game::new(8);
+            game.set_fen("W:W22:B13,14,15").unwrap();
+            game.set_quiescence(enabled, 8);
+            game.best_move(0, i32::MIN, i32::MAX, 0).deep_eval
+        };
+        assert!(leaf_eval(true) < leaf_eval(false) - 500);
+    }
+
+    #[test]
+    fn parallel_root() {
+        fn thread_safe<T: Send + Sync>() {}
+        thread_safe::<crate::position::Position>();
+        for fen in ["W:W21-32:B1-12", "B:W18,21,22,24,25,26,27,29,30,31:B1,2,3,5,6,7,9,10,11,13",
+                    "W:WK5,22,26,30:B9,13,15,16,K27"] {
+            let mut serial = Game::new(8);
+            serial.set_fen(fen).unwrap();
+            let expected = serial.best_move(4, i32::MIN, i32::MAX, 0).deep_eval;
+            let mut game = Game::new(8);
+            game.set_fen(fen).unwrap();
+            let before = game.get_fen();
+            let best = game.best_move_parallel(4, 4);
+            assert_eq!(best.deep_eval, expected, "{}", fen);
+            assert!(game.search_stats().nodes > 0);
+            assert_eq!(game.get_fen(), before);
+            // the iterative search with threads completes the same depth
+            let mut game = Game::new(8);
+            game.set_fen(fen).unwrap();
+            let limits = SearchLimits { max_depth: 4, time_ms: None, max_nodes: None, threads: 4 };
+            assert_eq!(game.best_move_iterative(&limits).deep_eval, expected, "{}", fen);
+        }
+        // the node limit is shared by the workers
+        let mut game = game();
+        let limits = SearchLimits { max_depth: 30, time_ms: None, max_nodes: Some(20000), threads: 4 };
+        game.best_move_iterative(&limits);
+        assert!(game.search_stats().nodes <= 20000 + 4, "{}", game.search_stats().nodes);
+        assert_eq!(game.worker_tts.len(), 4);
+    }
+}
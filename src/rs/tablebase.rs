@@ -0,0 +1,573 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Write};
+use crate::bitboard::{BitGeometry, BitPosition};
+use crate::color::Color;
+use crate::position::{PieceCount, Position};
+use crate::rules::RuleSet;
+
+const MAGIC: &[u8; 4] = b"CKTB";
+const VERSION: u8 = 1;
+const SQUARES: usize = 32;
+// first and last rows of the packed 8x8 board
+const FIRST_ROW: u32 = 0x0000_000f;
+const LAST_ROW: u32 = 0xf000_0000;
+// score of a won position in the search, less the plies to the win
+pub const TB_WIN: i32 = 1 << 28;
+
+// Pieces of a table, those of the side to move first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Material {
+    pub men: u8,
+    pub kings: u8,
+    pub other_men: u8,
+    pub other_kings: u8,
+}
+
+impl Material {
+    pub fn count(&self) -> usize {
+        (self.men + self.kings + self.other_men + self.other_kings) as usize
+    }
+
+    fn mirror(&self) -> Material {
+        Material { men: self.other_men, kings: self.other_kings, other_men: self.men, other_kings: self.kings }
+    }
+
+    fn groups(&self) -> [usize; 4] {
+        [self.men as usize, self.kings as usize, self.other_men as usize, self.other_kings as usize]
+    }
+
+    // positions of the table, placements with men on their last rows included
+    pub fn size(&self) -> usize {
+        let mut free = SQUARES;
+        let mut size = 1;
+        for k in self.groups() {
+            size *= binomial(free, k);
+            free -= k;
+        }
+        size
+    }
+
+    // every material of 2 to `max_pieces` pieces with a piece on both sides, in the order
+    // they have to be solved: captures lower the count, promotions the number of men
+    pub fn all(max_pieces: usize) -> Vec<Material> {
+        let mut res = vec![];
+        let n = max_pieces.min(8) as u8;
+        for men in 0..=n {
+            for kings in 0..=n - men {
+                for other_men in 0..=n - men - kings {
+                    for other_kings in 0..=n - men - kings - other_men {
+                        let material = Material { men, kings, other_men, other_kings };
+                        if men + kings > 0 && other_men + other_kings > 0 { res.push(material); }
+                    }
+                }
+            }
+        }
+        res.sort_by_key(|x| (x.count(), x.men + x.other_men, *x));
+        res
+    }
+}
+
+// Squares (packed indices) of the pieces, the side to move moving up the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Placement {
+    groups: [u32; 4],
+}
+
+impl Placement {
+    fn material(&self) -> Material {
+        let c = self.groups.map(|x| x.count_ones() as u8);
+        Material { men: c[0], kings: c[1], other_men: c[2], other_kings: c[3] }
+    }
+
+    // men on the row they would have been crowned on
+    fn is_valid(&self) -> bool {
+        self.groups[0] & LAST_ROW == 0 && self.groups[2] & FIRST_ROW == 0
+    }
+
+    // the board turned around with the sides swapped: the other side to move
+    fn flip(&self) -> Placement {
+        let g = self.groups.map(|x| x.reverse_bits());
+        Placement { groups: [g[2], g[3], g[0], g[1]] }
+    }
+
+    fn index(&self) -> usize {
+        let material = self.material();
+        let mut used = 0;
+        let mut free = SQUARES;
+        let mut index = 0;
+        for (mask, k) in self.groups.iter().zip(material.groups()) {
+            index = index * binomial(free, k) + rank(*mask, used);
+            used |= mask;
+            free -= k;
+        }
+        index
+    }
+
+    fn from_index(material: &Material, mut index: usize) -> Placement {
+        let groups = material.groups();
+        let mut sizes = [0; 4];
+        let mut free = SQUARES;
+        for (size, k) in sizes.iter_mut().zip(groups) {
+            *size = binomial(free, k);
+            free -= k;
+        }
+        let mut ranks = [0; 4];
+        for i in (0..4).rev() {
+            ranks[i] = index % sizes[i];
+            index /= sizes[i];
+        }
+        let mut used = 0;
+        let mut res = Placement { groups: [0; 4] };
+        for i in 0..4 {
+            res.groups[i] = unrank(ranks[i], groups[i], used);
+            used |= res.groups[i];
+        }
+        res
+    }
+
+    // side to move first
+    fn from_bits(geometry: &BitGeometry, bits: &BitPosition) -> Placement {
+        let mask = |bits: u64| (0..SQUARES).filter(|p| bits & geometry.bit(*p) != 0)
+            .fold(0u32, |acc, p| acc | 1 << p);
+        let placement = Placement {
+            groups: [mask(bits.white & !bits.kings), mask(bits.white & bits.kings),
+                mask(bits.black & !bits.kings), mask(bits.black & bits.kings)],
+        };
+        if bits.next_move == Color::White { placement } else { placement.flip() }
+    }
+
+    // white to move
+    fn to_bits(self, geometry: &BitGeometry) -> BitPosition {
+        let bits = |mask: u32| (0..SQUARES).filter(|p| mask & 1 << p != 0)
+            .fold(0u64, |acc, p| acc | geometry.bit(p));
+        let mut res = BitPosition::new(Color::White);
+        res.white = bits(self.groups[0] | self.groups[1]);
+        res.black = bits(self.groups[2] | self.groups[3]);
+        res.kings = bits(self.groups[1] | self.groups[3]);
+        res
+    }
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n { return 0; }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
+
+// colex rank of the squares of `mask` among the squares not `used`
+fn rank(mask: u32, used: u32) -> usize {
+    let mut res = 0;
+    let mut i = 0;
+    for p in 0..SQUARES {
+        if mask & 1 << p == 0 { continue; }
+        i += 1;
+        let q = p - (used & ((1u32 << p) - 1)).count_ones() as usize;
+        res += binomial(q, i);
+    }
+    res
+}
+
+fn unrank(mut rank: usize, k: usize, used: u32) -> u32 {
+    let free: Vec<usize> = (0..SQUARES).filter(|p| used & 1 << p == 0).collect();
+    let mut res = 0;
+    for i in (1..=k).rev() {
+        let mut q = i - 1;
+        while binomial(q + 1, i) <= rank { q += 1; }
+        rank -= binomial(q, i);
+        res |= 1 << free[q];
+    }
+    res
+}
+
+// Runs of a table: a byte n < 128 is followed by n + 1 values, n >= 128 by a value
+// repeated n - 125 times
+fn encode(values: &[u8]) -> Vec<u8> {
+    let mut res = vec![];
+    let mut i = 0;
+    while i < values.len() {
+        let mut n = 1;
+        while i + n < values.len() && n < 130 && values[i + n] == values[i] { n += 1; }
+        if n >= 3 {
+            res.push((n + 125) as u8);
+            res.push(values[i]);
+            i += n;
+            continue;
+        }
+        // the values up to the next repeat
+        let start = i;
+        while i < values.len() && i - start < 128 {
+            if i + 2 < values.len() && values[i] == values[i + 1] && values[i] == values[i + 2] { break; }
+            i += 1;
+        }
+        res.push((i - start - 1) as u8);
+        res.extend_from_slice(&values[start..i]);
+    }
+    res
+}
+
+fn decode(runs: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut res = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < runs.len() {
+        let n = runs[i] as usize;
+        if n < 128 {
+            res.extend_from_slice(runs.get(i + 1..i + 2 + n)?);
+            i += n + 2;
+        } else {
+            res.extend(std::iter::repeat_n(*runs.get(i + 1)?, n - 125));
+            i += 2;
+        }
+    }
+    Some(res)
+}
+
+// value of a position for the side to move
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TbValue {
+    // plies to the end of the game with best play
+    Win(u8),
+    Loss(u8),
+    Draw,
+}
+
+impl TbValue {
+    // 0 for a draw or an unsolved position, plies + 1 otherwise: odd plies win, even lose
+    fn from_byte(byte: u8) -> TbValue {
+        match byte {
+            0 => TbValue::Draw,
+            x if (x - 1) % 2 == 1 => TbValue::Win(x - 1),
+            x => TbValue::Loss(x - 1),
+        }
+    }
+
+    // score of the search for the side to move at `ply` from the root, nearer wins higher
+    pub fn score(&self, ply: usize) -> i32 {
+        match self {
+            TbValue::Win(plies) => TB_WIN - (ply + *plies as usize) as i32,
+            TbValue::Loss(plies) => (ply + *plies as usize) as i32 - TB_WIN,
+            TbValue::Draw => 0,
+        }
+    }
+}
+
+// Position of a table being solved with what its moves into solved tables give
+struct Node {
+    table: usize,
+    index: usize,
+    moves: bool,
+    // range of the moves within the class
+    children: (usize, usize),
+    min_loss: Option<u8>,
+    max_win: u8,
+    all_win: bool,
+    external: u8,
+}
+
+// Win/loss/draw and distance to the win of every position with up to `max_pieces` pieces,
+// Russian rules on 8x8, found by retrograde analysis. The draw rules of finish_check
+// (triangle, main road, move counts) are not applied: a won position may take longer.
+pub struct Tablebase {
+    pub max_pieces: usize,
+    pub rules: RuleSet,
+    geometry: BitGeometry,
+    tables: HashMap<Material, Vec<u8>>,
+}
+
+impl Tablebase {
+    fn empty(max_pieces: usize) -> Tablebase {
+        let rules = RuleSet::russian(8);
+        Tablebase { max_pieces, geometry: BitGeometry::new(&rules).unwrap(), rules, tables: HashMap::new() }
+    }
+
+    pub fn generate(max_pieces: usize) -> Tablebase {
+        Tablebase::generate_with(max_pieces, |_, _| {})
+    }
+
+    // `on_table(material, positions)` after every table solved
+    pub fn generate_with(max_pieces: usize, mut on_table: impl FnMut(&Material, usize)) -> Tablebase {
+        let mut tb = Tablebase::empty(max_pieces);
+        for material in Material::all(max_pieces) {
+            if tb.tables.contains_key(&material) { continue; }
+            let mirror = material.mirror();
+            let class = if mirror == material { vec![material] } else { vec![material, mirror] };
+            tb.solve(&class);
+            for material in class {
+                on_table(&material, tb.tables[&material].len());
+            }
+        }
+        tb
+    }
+
+    // Tables of a material and its mirror, which reach each other by quiet moves, solved
+    // together. Pass n finds the positions won or lost in n plies from those of n - 1.
+    fn solve(&mut self, class: &[Material]) {
+        let mut values: Vec<Vec<u8>> = class.iter().map(|x| vec![0; x.size()]).collect();
+        // the moves of every position are generated once, those within the class are kept
+        // as the table (high bit) and index of the position they lead to
+        let mut open = vec![];
+        let mut children = vec![];
+        for (t, material) in class.iter().enumerate() {
+            for index in 0..material.size() {
+                let placement = Placement::from_index(material, index);
+                if !placement.is_valid() { continue; }
+                open.push(self.node(placement, t, index, class, &mut children));
+            }
+        }
+        let last_external = open.iter().map(|x| x.external).max().unwrap_or(0);
+        let mut pass = 0;
+        loop {
+            let mut changed = false;
+            open.retain(|node| {
+                let mut min_loss = node.min_loss;
+                let mut max_win = node.max_win;
+                let mut all_win = node.all_win;
+                for &child in &children[node.children.0..node.children.1] {
+                    let byte = values[(child >> 31) as usize][(child & !(1 << 31)) as usize];
+                    match TbValue::from_byte(byte) {
+                        TbValue::Loss(plies) => {
+                            min_loss = Some(min_loss.map_or(plies, |x: u8| x.min(plies)));
+                            all_win = false;
+                        }
+                        TbValue::Win(plies) => max_win = max_win.max(plies),
+                        TbValue::Draw => all_win = false,
+                    }
+                }
+                let decided = if !node.moves {
+                    pass == 0
+                } else {
+                    min_loss.is_some_and(|x| x as usize + 1 == pass) || (all_win && max_win as usize + 1 == pass)
+                };
+                if decided {
+                    values[node.table][node.index] = pass as u8 + 1;
+                    changed = true;
+                }
+                !decided
+            });
+            pass += 1;
+            // values of the other tables may still be reached at a later pass
+            if (!changed && pass > last_external as usize + 1) || pass >= u8::MAX as usize - 1 { break; }
+        }
+        for (material, values) in class.iter().zip(values) {
+            self.tables.insert(*material, values);
+        }
+    }
+
+    fn node(&self, placement: Placement, table: usize, index: usize, class: &[Material], children: &mut Vec<u32>)
+            -> Node {
+        let mut bits = placement.to_bits(&self.geometry);
+        let move_list = bits.get_move_list(&self.geometry, false);
+        let start = children.len();
+        let mut node = Node {
+            table, index, moves: !move_list.list.is_empty(), children: (start, start),
+            min_loss: None, max_win: 0, all_win: true, external: 0,
+        };
+        for move_item in &move_list.list {
+            let undo = bits.make_move(&self.geometry, move_item);
+            let child = Placement::from_bits(&self.geometry, &bits);
+            bits.unmake_move(&undo);
+            let material = child.material();
+            if let Some(t) = class.iter().position(|x| *x == material) {
+                children.push((t as u32) << 31 | child.index() as u32);
+                continue;
+            }
+            // no pieces left to move or a position of a solved table
+            let byte = if material.men + material.kings == 0 { 1 } else { self.tables[&material][child.index()] };
+            node.external = node.external.max(byte);
+            match TbValue::from_byte(byte) {
+                TbValue::Loss(plies) => {
+                    node.min_loss = Some(node.min_loss.map_or(plies, |x| x.min(plies)));
+                    node.all_win = false;
+                }
+                TbValue::Win(plies) => node.max_win = node.max_win.max(plies),
+                TbValue::Draw => node.all_win = false,
+            }
+        }
+        node.children.1 = children.len();
+        node
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    fn probe_placement(&self, placement: Placement) -> Option<TbValue> {
+        let material = placement.material();
+        if material.men + material.kings == 0 { return Some(TbValue::Loss(0)); }
+        let table = self.tables.get(&material)?;
+        Some(TbValue::from_byte(table[placement.index()]))
+    }
+
+    pub fn probe_bits(&self, bits: &BitPosition) -> Option<TbValue> {
+        self.probe_placement(Placement::from_bits(&self.geometry, bits))
+    }
+
+    // None if the position has more pieces or other rules than the tables
+    pub fn probe(&self, pos: &Position) -> Option<TbValue> {
+        if pos.environment.rules != self.rules { return None; }
+        let count = |x: &PieceCount| (x.simple + x.king) as usize;
+        if count(&pos.state.white) + count(&pos.state.black) > self.max_pieces { return None; }
+        self.probe_bits(&BitPosition::from_position(&self.geometry, pos))
+    }
+
+    // header, then every table as its material and its run-length encoded values
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION, self.rules.size as u8, self.max_pieces as u8])?;
+        w.write_all(&(self.tables.len() as u32).to_le_bytes())?;
+        let mut materials: Vec<_> = self.tables.keys().collect();
+        materials.sort();
+        for material in materials {
+            let values = &self.tables[material];
+            let runs = encode(values);
+            w.write_all(&[material.men, material.kings, material.other_men, material.other_kings])?;
+            w.write_all(&(values.len() as u32).to_le_bytes())?;
+            w.write_all(&(runs.len() as u32).to_le_bytes())?;
+            w.write_all(&runs)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Tablebase> {
+        let invalid = |text: &str| io::Error::new(io::ErrorKind::InvalidData, text.to_string());
+        let mut header = [0u8; 11];
+        r.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC || header[4] != VERSION { return Err(invalid("not a tablebase file")); }
+        if header[5] != 8 { return Err(invalid("only 8x8 tables are supported")); }
+        let mut tb = Tablebase::empty(header[6] as usize);
+        let count = u32::from_le_bytes(header[7..11].try_into().unwrap());
+        for _ in 0..count {
+            let mut head = [0u8; 12];
+            r.read_exact(&mut head)?;
+            let material = Material { men: head[0], kings: head[1], other_men: head[2], other_kings: head[3] };
+            let len = u32::from_le_bytes(head[4..8].try_into().unwrap()) as usize;
+            let mut runs = vec![0u8; u32::from_le_bytes(head[8..12].try_into().unwrap()) as usize];
+            r.read_exact(&mut runs)?;
+            let values = decode(&runs, len).ok_or_else(|| invalid("truncated table"))?;
+            if values.len() != len || len != material.size() { return Err(invalid("table size mismatch")); }
+            tb.tables.insert(material, values);
+        }
+        Ok(tb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, OnceLock};
+    use crate::bitboard::BitPosition;
+    use crate::game::Game;
+    use crate::tablebase::{Material, Placement, Tablebase, TbValue, TB_WIN};
+
+    fn tablebase() -> &'static Tablebase {
+        static TB: OnceLock<Tablebase> = OnceLock::new();
+        TB.get_or_init(|| Tablebase::generate(3))
+    }
+
+    #[test]
+    fn index() {
+        let material = Material { men: 1, kings: 1, other_men: 0, other_kings: 1 };
+        assert_eq!(material.size(), 32 * 31 * 30);
+        for index in [0, 1, 777, material.size() - 1] {
+            let placement = Placement::from_index(&material, index);
+            assert_eq!(placement.material(), material);
+            assert_eq!(placement.index(), index);
+            assert_eq!(placement.flip().flip(), placement);
+        }
+        let all = Material::all(3);
+        // 1 vs 1 of men and kings, 2 vs 1 and 1 vs 2 of 3 pieces
+        assert_eq!(all.len(), 4 + 2 * 6);
+        assert!(all.windows(2).all(|x| x[0].count() <= x[1].count()));
+    }
+
+    // every value follows from the values of the moves
+    #[test]
+    fn consistent() {
+        let tb = tablebase();
+        assert_eq!(tb.len(), 16);
+        let mut decided = 0;
+        for material in Material::all(3) {
+            for index in 0..material.size() {
+                let placement = Placement::from_index(&material, index);
+                if !placement.is_valid() { continue; }
+                let value = tb.probe_placement(placement).unwrap();
+                let mut bits = placement.to_bits(&tb.geometry);
+                let children: Vec<_> = bits.get_move_list(&tb.geometry, false).list.iter().map(|move_item| {
+                    let undo = bits.make_move(&tb.geometry, move_item);
+                    let child = tb.probe_bits(&bits).unwrap();
+                    bits.unmake_move(&undo);
+                    child
+                }).collect();
+                let losses = children.iter().filter_map(|x| if let TbValue::Loss(p) = x { Some(*p) } else { None });
+                let expected = match losses.min() {
+                    Some(plies) => TbValue::Win(plies + 1),
+                    None if children.iter().all(|x| matches!(x, TbValue::Win(_))) => {
+                        let max = children.iter().map(|x| if let TbValue::Win(p) = x { *p as i32 } else { -1 }).max();
+                        TbValue::Loss(max.map_or(0, |x| x as u8 + 1))
+                    }
+                    None => TbValue::Draw
+                };
+                assert_eq!(value, expected, "{:?} {}", material, index);
+                if value != TbValue::Draw { decided += 1; }
+            }
+        }
+        assert!(decided > 0);
+    }
+
+    #[test]
+    fn probe_positions() {
+        let tb = tablebase();
+        let probe = |fen: &str| {
+            let mut game = Game::new(8);
+            game.set_fen(fen).unwrap();
+            tb.probe(&game.current_position)
+        };
+        // c3:e5 takes the last piece
+        assert_eq!(probe("W:WK22:B18"), Some(TbValue::Win(1)));
+        assert_eq!(probe("B:WK22:B18"), probe("W:W15:BK11"));
+        // two kings don't catch a king on the long diagonal
+        assert_eq!(probe("W:WK1,K3:BK29"), Some(TbValue::Draw));
+        assert_eq!(probe("W:WK1,K3:BK29,30"), None);
+        let mut international = Game::with_variant(crate::rules::Variant::International);
+        international.set_fen("W:W22:B18").unwrap();
+        assert_eq!(tb.probe(&international.current_position), None);
+        // the same from the bit position
+        let mut game = Game::new(8);
+        game.set_fen("W:WK22:B18").unwrap();
+        let bits = BitPosition::from_position(&tb.geometry, &game.current_position);
+        assert_eq!(tb.probe_bits(&bits), Some(TbValue::Win(1)));
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let tb = tablebase();
+        let mut bytes = vec![];
+        tb.write(&mut bytes).unwrap();
+        let raw: usize = Material::all(3).iter().map(|x| x.size()).sum();
+        assert!(bytes.len() < raw * 2 / 3, "{} of {}", bytes.len(), raw);
+        let read = Tablebase::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.max_pieces, 3);
+        assert_eq!(read.tables, tb.tables);
+        assert!(Tablebase::read(&mut &bytes[..20]).is_err());
+        assert!(Tablebase::read(&mut &b"CKTB\x09"[..]).is_err());
+    }
+
+    #[test]
+    fn search() {
+        let tb = Arc::new(Tablebase::generate(3));
+        // a king and a man against a man: the search finds a table win
+        let mut game = Game::new(8);
+        game.set_fen("W:WK10,21:B12").unwrap();
+        game.set_tablebase(Some(tb.clone()));
+        let best = game.best_move(2, i32::MIN, i32::MAX, 0);
+        let value = tb.probe(&game.current_position).unwrap();
+        assert!(matches!(value, TbValue::Win(_)), "{:?}", value);
+        assert_eq!(best.deep_eval, value.score(0));
+        assert!(best.deep_eval > TB_WIN - 100);
+        // the move keeps the win
+        game.make_move_by_pos_item(&best);
+        assert!(matches!(tb.probe(&game.current_position), Some(TbValue::Loss(_))));
+    }
+}
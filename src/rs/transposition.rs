@@ -0,0 +1,129 @@
+use std::mem::size_of;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    // score is at least the stored one (cut off by beta)
+    Lower,
+    // score is at most the stored one (no move raised alpha)
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TtEntry {
+    pub key: u64,
+    pub score: i32,
+    // plies searched below the position
+    pub draft: i16,
+    pub bound: Bound,
+    // index of the best move in the position's move list, u16::MAX if none
+    pub best: u16,
+}
+
+impl TtEntry {
+    const EMPTY: TtEntry = TtEntry { key: 0, score: 0, draft: -1, bound: Bound::Exact, best: u16::MAX };
+
+    pub fn best_move(&self) -> Option<usize> {
+        if self.best == u16::MAX { None } else { Some(self.best as usize) }
+    }
+}
+
+// fixed size table indexed by the low bits of the Zobrist key
+#[derive(Clone, Debug)]
+pub struct TranspositionTable {
+    // allocated by the first store, games that never search don't pay for it
+    entries: Vec<TtEntry>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> TranspositionTable {
+        let count = (size_mb * 1024 * 1024 / size_of::<TtEntry>()).max(1);
+        // round down to a power of two
+        let count = 1 << (usize::BITS - 1 - count.leading_zeros());
+        TranspositionTable { entries: vec![], mask: count - 1 }
+    }
+
+    // table of the same size with nothing stored
+    pub fn empty_copy(&self) -> TranspositionTable {
+        TranspositionTable { entries: vec![], mask: self.mask }
+    }
+
+    pub fn len(&self) -> usize {
+        self.mask + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(TtEntry::EMPTY);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<&TtEntry> {
+        self.entries.get(key as usize & self.mask).filter(|x| x.key == key && x.draft >= 0)
+    }
+
+    // a deeper result for the same position is kept, other positions are replaced
+    pub fn store(&mut self, key: u64, draft: i16, bound: Bound, score: i32, best: Option<usize>) {
+        if self.entries.is_empty() {
+            self.entries = vec![TtEntry::EMPTY; self.len()];
+        }
+        let entry = &mut self.entries[key as usize & self.mask];
+        if entry.key == key && entry.draft > draft { return; }
+        *entry = TtEntry {
+            key,
+            score,
+            draft,
+            bound,
+            best: best.map_or(u16::MAX, |x| x as u16),
+        };
+    }
+
+    // bound of a fail-soft search between alpha and beta
+    pub fn bound(score: i32, alpha: i32, beta: i32) -> Bound {
+        if score <= alpha { Bound::Upper } else if score >= beta { Bound::Lower } else { Bound::Exact }
+    }
+
+    // stored score that settles the search between alpha and beta
+    pub fn cutoff(entry: &TtEntry, draft: i16, alpha: i32, beta: i32) -> Option<i32> {
+        if entry.draft < draft { return None; }
+        match entry.bound {
+            Bound::Exact => Some(entry.score),
+            Bound::Lower if entry.score >= beta => Some(entry.score),
+            Bound::Upper if entry.score <= alpha => Some(entry.score),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transposition::{Bound, TranspositionTable};
+
+    #[test]
+    fn store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        assert!(tt.len().is_power_of_two());
+        let key = 0x1234_5678_9abc_def0;
+        assert!(tt.probe(key).is_none());
+        assert!(tt.entries.is_empty());
+        tt.store(key, 3, Bound::Lower, 50, Some(2));
+        let entry = *tt.probe(key).unwrap();
+        assert_eq!(entry.best_move(), Some(2));
+        assert_eq!(TranspositionTable::cutoff(&entry, 3, 0, 40), Some(50));
+        assert_eq!(TranspositionTable::cutoff(&entry, 3, 0, 60), None);
+        assert_eq!(TranspositionTable::cutoff(&entry, 4, 0, 40), None);
+        // shallower result for the same position doesn't replace a deeper one
+        tt.store(key, 1, Bound::Exact, 10, None);
+        assert_eq!(tt.probe(key).unwrap().score, 50);
+        // other position in the same slot does
+        let other = key + tt.len() as u64;
+        tt.store(other, 1, Bound::Upper, -5, None);
+        assert!(tt.probe(key).is_none());
+        assert_eq!(tt.probe(other).unwrap().best_move(), None);
+        tt.clear();
+        assert!(tt.probe(other).is_none());
+    }
+}
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+use rand::Rng;
+use crate::eval::{EvalParams, WEIGHTS};
+use crate::game::Game;
+use crate::position::Position;
+use crate::PositionHistory::FinishType;
+
+// plies after which an unfinished self-play game counts as a draw
+const MAX_PLIES: usize = 300;
+
+// 1 for a white win, 0 for a black one
+pub fn result_score(finish: &FinishType) -> f64 {
+    match finish {
+        FinishType::WhiteWin => 1.0,
+        FinishType::BlackWin => 0.0,
+        _ => 0.5
+    }
+}
+
+// Quiet position of a finished game. The evaluation is linear in the weights, so the
+// position is kept as the evaluation of each weight alone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub features: [i32; WEIGHTS],
+    pub result: f64,
+}
+
+impl Sample {
+    pub fn new(pos: &Position, result: f64) -> Sample {
+        let mut features = [0; WEIGHTS];
+        for (i, feature) in features.iter_mut().enumerate() {
+            let mut unit = [0; WEIGHTS];
+            unit[i] = 1;
+            let mut environment = (*pos.environment).clone();
+            environment.eval = EvalParams::from_weights(unit);
+            let mut pos = pos.clone();
+            pos.environment = Arc::new(environment);
+            pos.eval = None;
+            *feature = pos.evaluate();
+        }
+        Sample { features, result }
+    }
+
+    pub fn eval(&self, weights: &[i32; WEIGHTS]) -> i32 {
+        self.features.iter().zip(weights).map(|(f, w)| f * w).sum()
+    }
+}
+
+// Games of `game` searched to `depth` against itself from its current position, each
+// opened by `random_plies` random moves. Positions without strikes are sampled with the
+// result the game ended with.
+pub fn self_play(game: &Game, games: usize, depth: i16, random_plies: usize, rng: &mut impl Rng) -> Vec<Sample> {
+    let mut samples = vec![];
+    for _ in 0..games {
+        let mut play = Game::with_rules(game.position_environment.rules.clone());
+        play.set_eval_params_rust(game.eval_params().clone());
+        play.set_fen(&game.get_fen()).unwrap();
+        let mut positions = vec![];
+        let mut finish = None;
+        for ply in 0..MAX_PLIES {
+            let list = play.current_position.get_move_list_cached();
+            let list = &list.as_ref().as_ref().unwrap().list;
+            if list.is_empty() { break; }
+            if list[0].strike.is_none() && ply >= random_plies {
+                positions.push(play.current_position.clone());
+            }
+            if ply < random_plies {
+                let move_item = list[rng.gen_range(0..list.len())].clone();
+                play.make_move_by_move_item(&move_item);
+            } else {
+                let best = play.best_move(depth, i32::MIN, i32::MAX, 0);
+                play.make_move_by_pos_item(&best);
+            }
+            finish = play.position_history.borrow_mut().finish_check();
+            if finish.is_some() { break; }
+        }
+        let result = finish.as_ref().map_or(0.5, result_score);
+        samples.extend(positions.iter().map(|pos| Sample::new(pos, result)));
+    }
+    samples
+}
+
+// expected score of white for an evaluation, `k` scales the evaluation to a logit
+pub fn sigmoid(eval: f64, k: f64) -> f64 {
+    1.0 / (1.0 + (-k * eval).exp())
+}
+
+// mean squared difference of the results and the expected scores
+pub fn error(samples: &[Sample], weights: &[i32; WEIGHTS], k: f64) -> f64 {
+    if samples.is_empty() { return 0.0; }
+    samples.iter().map(|x| (x.result - sigmoid(x.eval(weights) as f64, k)).powi(2)).sum::<f64>()
+        / samples.len() as f64
+}
+
+// scaling constant with the least error for the weights, searched on a log scale
+pub fn fit_k(samples: &[Sample], weights: &[i32; WEIGHTS]) -> f64 {
+    let (mut lo, mut hi) = (-8.0_f64, 0.0_f64);
+    // ternary search of the exponent of ten
+    for _ in 0..60 {
+        let a = lo + (hi - lo) / 3.0;
+        let b = hi - (hi - lo) / 3.0;
+        if error(samples, weights, 10f64.powf(a)) < error(samples, weights, 10f64.powf(b)) { hi = b; } else { lo = a; }
+    }
+    10f64.powf((lo + hi) / 2.0)
+}
+
+// Texel's local search: every weight but the value of a man, which sets the scale, is moved
+// by `step` while that lowers the error, then by half the step, down to 1. Returns the
+// tuned parameters and their error.
+pub fn tune(samples: &[Sample], params: &EvalParams, k: f64, mut step: i32, mut on_pass: impl FnMut(usize, f64))
+    -> (EvalParams, f64) {
+    let mut weights = params.weights();
+    let mut best = error(samples, &weights, k);
+    let mut pass = 0;
+    while step > 0 {
+        let mut improved = false;
+        for i in 1..WEIGHTS {
+            for delta in [step, -step] {
+                let mut next = weights;
+                next[i] += delta;
+                let e = error(samples, &next, k);
+                if e < best {
+                    best = e;
+                    weights = next;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        pass += 1;
+        on_pass(pass, best);
+        if !improved { step /= 2; }
+    }
+    (EvalParams::from_weights(weights), best)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::eval::EvalParams;
+    use crate::game::Game;
+    use crate::tuning::{error, fit_k, self_play, tune, Sample};
+
+    #[test]
+    fn features() {
+        let params = EvalParams { advancement: 7, center: 11, back_rank: -5, tempo: 3, cell_grade: 2, ..EvalParams::default() };
+        for fen in ["W:W21-32:B1-12", "B:W18,21,22,24,25,26,27,29,30,31:B1,2,3,5,6,7,9,10,11,13",
+                    "W:WK5,22,26,30:B9,13,15,16,K27"] {
+            let mut game = Game::new(8);
+            game.set_eval_params_rust(params.clone());
+            game.set_fen(fen).unwrap();
+            let sample = Sample::new(&game.current_position, 0.5);
+            assert_eq!(sample.eval(&params.weights()), game.current_position.evaluate(), "{}", fen);
+        }
+        assert_eq!(EvalParams::from_weights(params.weights()), params);
+    }
+
+    #[test]
+    fn self_play_samples() {
+        let mut game = Game::new(8);
+        game.set_start_position();
+        let samples = self_play(&game, 2, 1, 4, &mut StdRng::seed_from_u64(1));
+        assert!(!samples.is_empty());
+        assert!(samples.iter().all(|x| [0.0, 0.5, 1.0].contains(&x.result)));
+        // men count up to 12 a side
+        assert!(samples.iter().all(|x| x.features[0].abs() <= 12));
+    }
+
+    #[test]
+    fn tuning_lowers_error() {
+        // white wins where it has more kings, whatever the men say
+        let sample = |men: i32, kings: i32, result: f64| {
+            Sample { features: [men, kings, 0, 0, 0, 0, 0, 0], result }
+        };
+        let samples = vec![sample(1, 1, 1.0), sample(2, -1, 0.0), sample(-1, 1, 1.0),
+                           sample(0, -1, 0.0), sample(1, 0, 0.5), sample(-1, 0, 0.5)];
+        let params = EvalParams::default();
+        let k = fit_k(&samples, &params.weights());
+        let before = error(&samples, &params.weights(), k);
+        let mut passes = 0;
+        let (tuned, after) = tune(&samples, &params, k, 256, |_, _| passes += 1);
+        assert!(passes > 0);
+        assert!(after < before);
+        assert_eq!(tuned.man, params.man);
+        assert!(tuned.king > params.king);
+    }
+}
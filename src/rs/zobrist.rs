@@ -0,0 +1,105 @@
+use crate::color::Color;
+use crate::moves::BoardPos;
+use crate::piece::Piece;
+
+// enough for boards up to 16x16
+const SQUARES: usize = 128;
+
+// fixed keys, so hashes stay the same between runs and can be stored
+const fn split_mix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    (state, z ^ (z >> 31))
+}
+
+const fn piece_keys() -> [[u64; 4]; SQUARES] {
+    let mut keys = [[0; 4]; SQUARES];
+    let mut state = 0x5eed_c4ec_4e85_0001;
+    let mut i = 0;
+    while i < SQUARES * 4 {
+        let (next, key) = split_mix(state);
+        state = next;
+        keys[i / 4][i % 4] = key;
+        i += 1;
+    }
+    keys
+}
+
+static PIECE_KEYS: [[u64; 4]; SQUARES] = piece_keys();
+
+pub const BLACK_TO_MOVE: u64 = split_mix(0x5eed_c4ec_4e85_0000).1;
+
+pub fn piece_key(pos: BoardPos, color: Color, is_king: bool) -> u64 {
+    let kind = if color == Color::White { 0 } else { 2 } + is_king as usize;
+    PIECE_KEYS[pos][kind]
+}
+
+pub fn side_key(next_move: Option<Color>) -> u64 {
+    if next_move == Some(Color::Black) { BLACK_TO_MOVE } else { 0 }
+}
+
+impl Piece {
+    pub fn zobrist(&self) -> u64 {
+        piece_key(self.pos, self.color, self.is_king)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+    use crate::position::Position;
+    use crate::position_environment::PositionEnvironment;
+    use crate::rules::{RuleSet, Variant};
+
+    #[test]
+    fn incremental_hash() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for variant in [Variant::Russian, Variant::International, Variant::English] {
+            let env = Arc::new(PositionEnvironment::with_rules(RuleSet::new(variant)));
+            for _game in 0..20 {
+                let mut pos = Position::start_position(env.clone());
+                let mut stack = vec![];
+                for _ply in 0..150 {
+                    assert_eq!(pos.zobrist(), pos.zobrist_by_cells());
+                    let list = pos.get_move_list(false);
+                    if list.list.is_empty() { break; }
+                    let mov = list.list[rng.gen_range(0..list.list.len())].clone();
+                    let hash = pos.zobrist();
+                    pos.make_move(&mov);
+                    stack.push((hash, pos.took_pieces.clone(), mov));
+                }
+                while let Some((hash, took_pieces, mov)) = stack.pop() {
+                    pos.took_pieces = took_pieces;
+                    pos.unmake_move(&mov);
+                    assert_eq!(pos.zobrist(), hash);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transpositions() {
+        let env = Arc::new(PositionEnvironment::new(8));
+        let mut a = Position::from_fen(env.clone(), "W:W29,30:B1,2").unwrap();
+        let mut b = a.clone();
+        for text in ["29-25", "1-5", "30-26", "2-6"] {
+            let mov = a.get_move_list(true).find_move(&env, text).unwrap().clone();
+            a.make_move(&mov);
+        }
+        for text in ["30-26", "2-6", "29-25", "1-5"] {
+            let mov = b.get_move_list(true).find_move(&env, text).unwrap().clone();
+            b.make_move(&mov);
+        }
+        assert_eq!(a.zobrist(), b.zobrist());
+        assert_eq!(a.zobrist(), Position::from_fen(env.clone(), &a.to_fen()).unwrap().zobrist());
+        a.next_move = Some(!a.next_move.unwrap());
+        assert_ne!(a.zobrist(), b.zobrist());
+        assert!(b.remove_piece(env.numeric_to_pack(25).unwrap()));
+        assert_ne!(a.zobrist(), b.zobrist());
+        assert_eq!(b.zobrist(), b.zobrist_by_cells());
+    }
+}